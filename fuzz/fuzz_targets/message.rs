@@ -0,0 +1,15 @@
+#![no_main]
+use katcp::protocol::{message, message_bytes, Message};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The parsers should never panic, whatever they're given
+    let _ = message_bytes(data);
+    if let Ok(s) = core::str::from_utf8(data) {
+        // And anything they accept should be serialized into something that parses back the same
+        if let Ok((_, parsed)) = message(s) {
+            let raw = parsed.to_string();
+            assert_eq!(Ok(parsed), Message::try_from(raw.as_str()), "{:?}", raw);
+        }
+    }
+});
@@ -0,0 +1,52 @@
+#![no_main]
+use arbitrary::Arbitrary;
+use katcp::messages::{common::roundtrip_test, core::*, log::*, multi_client::*, sensors::*};
+use libfuzzer_sys::fuzz_target;
+
+/// Every message type, so a single target covers all of them
+#[derive(Arbitrary, Debug)]
+enum AnyMessage {
+    Halt(Halt),
+    Help(Help),
+    Restart(Restart),
+    Watchdog(Watchdog),
+    VersionList(VersionList),
+    Disconnect(Disconnect),
+    VersionConnect(VersionConnect),
+    Version(Version),
+    BuildState(BuildState),
+    InterfaceChanged(InterfaceChanged),
+    RequestTimeoutHint(RequestTimeoutHint),
+    Log(Log),
+    LogLevel(LogLevel),
+    ClientList(ClientList),
+    ClientConnected(ClientConnected),
+    SensorList(SensorList),
+    SensorSampling(SensorSampling),
+    SensorSamplingClear(SensorSamplingClear),
+    SensorValue(SensorValue),
+    SensorStatus(SensorStatus),
+}
+
+fuzz_target!(|message: AnyMessage| match message {
+    AnyMessage::Halt(m) => roundtrip_test(m),
+    AnyMessage::Help(m) => roundtrip_test(m),
+    AnyMessage::Restart(m) => roundtrip_test(m),
+    AnyMessage::Watchdog(m) => roundtrip_test(m),
+    AnyMessage::VersionList(m) => roundtrip_test(m),
+    AnyMessage::Disconnect(m) => roundtrip_test(m),
+    AnyMessage::VersionConnect(m) => roundtrip_test(m),
+    AnyMessage::Version(m) => roundtrip_test(m),
+    AnyMessage::BuildState(m) => roundtrip_test(m),
+    AnyMessage::InterfaceChanged(m) => roundtrip_test(m),
+    AnyMessage::RequestTimeoutHint(m) => roundtrip_test(m),
+    AnyMessage::Log(m) => roundtrip_test(m),
+    AnyMessage::LogLevel(m) => roundtrip_test(m),
+    AnyMessage::ClientList(m) => roundtrip_test(m),
+    AnyMessage::ClientConnected(m) => roundtrip_test(m),
+    AnyMessage::SensorList(m) => roundtrip_test(m),
+    AnyMessage::SensorSampling(m) => roundtrip_test(m),
+    AnyMessage::SensorSamplingClear(m) => roundtrip_test(m),
+    AnyMessage::SensorValue(m) => roundtrip_test(m),
+    AnyMessage::SensorStatus(m) => roundtrip_test(m),
+});
@@ -5,187 +5,405 @@ use proc_macro::TokenStream;
 use proc_macro2::Ident;
 use quote::{format_ident, quote};
 use syn::{
-    parse_macro_input, DataEnum, DeriveInput, Fields, FieldsNamed, FieldsUnnamed, Type, Variant,
+    ext::IdentExt, parse_macro_input, Attribute, DataEnum, DeriveInput, Fields, FieldsUnnamed,
+    GenericArgument, Lit, LitByteStr, LitStr, Member, Meta, NestedMeta, PathArguments, Type,
+    Variant,
 };
 
-fn sort_variants(variants: Vec<Variant>) -> (Option<Variant>, Option<Variant>, Option<Variant>) {
-    assert!(
-        (1..=4).contains(&variants.len()),
-        "There must be between 1 and 3 variants"
-    );
+#[derive(Default)]
+/// The options given in `#[katcp(...)]` attributes
+struct KatcpAttributes {
+    /// `name = "..."` on a message enum, overriding the wire name
+    name: Option<LitStr>,
+    /// `rename = "..."` on a discrete variant, overriding its wire value
+    rename: Option<LitStr>,
+    /// `skip_if_none` on an `Option` field, leaving out the argument entirely rather than sending `\@`
+    skip_if_none: bool,
+    /// `allow_extra` on a message enum or one of its variants, ignoring surplus arguments when decoding
+    allow_extra: bool,
+    /// `usage = "..."` on a message enum, overriding the description in its `#help` inform
+    usage: Option<LitStr>,
+    /// `tag = "..."` on an arguments enum, naming its leading argument in errors
+    tag: Option<LitStr>,
+    /// `flatten` on an arguments field, taking its arguments with To/FromKatcpArguments
+    flatten: bool,
+    /// `validate = "..."` on an arguments struct, naming a function that checks it once it's been decoded
+    validate: Option<LitStr>,
+}
+
+/// Collects the `#[katcp(...)]` attributes in `attrs`, only accepting the options in `allowed`
+fn katcp_attributes(attrs: &[Attribute], allowed: &[&str]) -> syn::Result<KatcpAttributes> {
+    let mut parsed = KatcpAttributes::default();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("katcp")) {
+        let nested = match attr.parse_meta()? {
+            Meta::List(list) => list.nested,
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "expected an attribute like `#[katcp(...)]`",
+                ))
+            }
+        };
+        for meta in nested {
+            let key = match &meta {
+                NestedMeta::Meta(meta) => meta.path().get_ident().map(|ident| ident.to_string()),
+                NestedMeta::Lit(_) => None,
+            }
+            .unwrap_or_default();
+            if !allowed.contains(&key.as_str()) {
+                let message = if allowed.is_empty() {
+                    "unexpected katcp attribute, none are allowed here".to_owned()
+                } else {
+                    format!(
+                        "unexpected katcp attribute, expected one of: {}",
+                        allowed.join(", ")
+                    )
+                };
+                return Err(syn::Error::new_spanned(meta, message));
+            }
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(pair)) => match pair.lit {
+                    Lit::Str(value) if key == "name" => parsed.name = Some(value),
+                    Lit::Str(value) if key == "rename" => parsed.rename = Some(value),
+                    Lit::Str(value) if key == "tag" => parsed.tag = Some(value),
+                    Lit::Str(value) if key == "usage" => parsed.usage = Some(value),
+                    Lit::Str(value) if key == "validate" => parsed.validate = Some(value),
+                    lit => {
+                        return Err(syn::Error::new_spanned(
+                            lit,
+                            format!("expected `{} = \"...\"`", key),
+                        ))
+                    }
+                },
+                NestedMeta::Meta(Meta::Path(_)) if key == "skip_if_none" => {
+                    parsed.skip_if_none = true
+                }
+                NestedMeta::Meta(Meta::Path(_)) if key == "allow_extra" => {
+                    parsed.allow_extra = true
+                }
+                NestedMeta::Meta(Meta::Path(_)) if key == "flatten" => parsed.flatten = true,
+                meta => {
+                    return Err(syn::Error::new_spanned(
+                        meta,
+                        format!("unexpected form for the katcp attribute `{}`", key),
+                    ))
+                }
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+/// The text of the `///` comments in `attrs` as one line, as a help description is a single argument
+fn doc_comment(attrs: &[Attribute]) -> String {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(pair)) => match pair.lit {
+                Lit::Str(line) => Some(line.value().trim().to_owned()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join(" ")
+}
+
+/// `doc` as plain text, as a `#help` description is read as it is: code spans lose their backticks and links keep only
+/// their text. Other brackets are left alone, as they're how usage strings mark optional arguments.
+fn strip_markup(doc: &str) -> String {
+    let mut plain = String::with_capacity(doc.len());
+    let mut rest = doc;
+    while let Some(c) = rest.chars().next() {
+        if c == '[' {
+            if let Some((text, after)) = split_link(rest) {
+                plain.extend(text.chars().filter(|&c| c != '`'));
+                rest = after;
+                continue;
+            }
+        }
+        if c != '`' {
+            plain.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    plain
+}
+
+/// Splits the markdown link at the start of `s` into its text and whatever follows the link
+fn split_link(s: &str) -> Option<(&str, &str)> {
+    let (text, after) = s[1..].split_once(']')?;
+    for (open, close) in [('(', ')'), ('[', ']')] {
+        if let Some(target) = after.strip_prefix(open) {
+            return target.split_once(close).map(|(_, after)| (text, after));
+        }
+    }
+    // Without a target, it's only a link if it looks like a path to an item, e.g. [`Help`] or [core::Help]
+    let is_item = (text.starts_with('`') && text.ends_with('`'))
+        || text.contains("::")
+        || text.starts_with(|c: char| c.is_ascii_uppercase());
+    if is_item {
+        Some((text, after))
+    } else {
+        None
+    }
+}
+
+/// Whether `name` follows the grammar of message names
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Whether `ty` is syntactically an `Option`
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map_or(false, |segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
+/// The `T` of an `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if segment.ident == "Option" => {
+            match args.args.first()? {
+                GenericArgument::Type(inner) => Some(inner),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Adds `error` to the errors collected so far, so they can all be reported at once
+fn push_error(errors: &mut Option<syn::Error>, error: syn::Error) {
+    match errors {
+        Some(errors) => errors.combine(error),
+        None => *errors = Some(error),
+    }
+}
+
+fn sort_variants(
+    message_name: &Ident,
+    variants: Vec<Variant>,
+) -> syn::Result<(Option<Variant>, Option<Variant>, Option<Variant>)> {
+    if variants.is_empty() {
+        return Err(syn::Error::new_spanned(
+            message_name,
+            "KatcpMessage needs at least one of the variants `Request`, `Reply` or `Inform`",
+        ));
+    }
+    let mut errors = None;
     let mut sorted = HashMap::new();
-    variants.into_iter().for_each(|variant| {
-        sorted.insert(variant.ident.to_string(), variant);
-    });
-    (
+    for variant in variants {
+        if let Err(e) = katcp_attributes(&variant.attrs, &["allow_extra"]) {
+            push_error(&mut errors, e);
+        }
+        let name = variant.ident.to_string();
+        if !matches!(name.as_str(), "Request" | "Reply" | "Inform") {
+            push_error(
+                &mut errors,
+                syn::Error::new_spanned(
+                    &variant.ident,
+                    format!(
+                        "unknown variant `{}`, expected one of `Request`, `Reply` or `Inform`",
+                        name
+                    ),
+                ),
+            );
+        }
+        sorted.insert(name, variant);
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+    Ok((
         sorted.get("Request").cloned(),
         sorted.get("Reply").cloned(),
         sorted.get("Inform").cloned(),
-    )
+    ))
 }
 
-/// In the named case, we simply will call To/FromKatcpArgument for every field
-fn get_named_field_types_and_names(variant: &Variant) -> Option<Vec<(Ident, Type)>> {
-    if let Fields::Named(FieldsNamed { named, .. }) = variant.fields.clone() {
-        Some(
-            named
-                .iter()
-                // We can unwrap here because we've already checked that the fields are named in the if let
-                .map(|f| (f.ident.to_owned().unwrap(), f.ty.to_owned()))
-                .collect(),
-        )
-    } else {
-        None
+/// The fields of a message variant with named fields, which are each a single argument unless they're flattened
+fn get_named_fields(fields: &Fields) -> syn::Result<Vec<ArgumentField>> {
+    let fields = argument_fields(fields)?;
+    if let Some((_, rest)) = fields.split_last() {
+        if let Some(field) = rest.iter().find(|f| f.flatten) {
+            return Err(syn::Error::new_spanned(
+                &field.span,
+                "only the last field of a message can have `#[katcp(flatten)]`",
+            ));
+        }
     }
+    Ok(fields)
 }
 
 // In the unnamed case, there will be exactly zero or one field
 // In the zero case, its an empty arugment list, in the one case we call To/FromKatcpArguments
 // to support non CFGs
-fn get_unnamed_field_types(variant: &Variant) -> Option<Vec<Type>> {
-    if let Fields::Unnamed(FieldsUnnamed { unnamed, .. }) = variant.fields.clone() {
-        Some(unnamed.iter().map(|f| f.ty.to_owned()).collect())
-    } else {
-        None
+fn get_unnamed_field(fields: &Fields, unnamed: &FieldsUnnamed) -> syn::Result<Vec<ArgumentField>> {
+    for f in &unnamed.unnamed {
+        katcp_attributes(&f.attrs, &[])?;
+    }
+    if let Some(extra) = unnamed.unnamed.iter().nth(1) {
+        return Err(syn::Error::new_spanned(
+            extra,
+            "unnamed variants can have at most one field, use named fields for more arguments",
+        ));
     }
+    // The field is the whole variant, so errors are only about the message
+    let mut fields = argument_fields(fields)?;
+    for field in &mut fields {
+        field.flatten = true;
+        field.name = None;
+    }
+    Ok(fields)
 }
 
-fn generate_named_serde(variant: &Variant, fields: Vec<(Ident, Type)>) -> proc_macro2::TokenStream {
-    let kind = variant.ident.to_owned();
-    // Two function names
-    let fn_to_variant = format_ident!(
-        "to_{}_variant",
-        variant.ident.to_owned().to_string().to_lowercase()
-    );
-    let fn_to_message_args = format_ident!(
-        "to_{}_message_args",
-        variant.ident.to_owned().to_string().to_lowercase()
-    );
-    // Iterator for Message -> Variant fn
-    let arg_parses = fields.iter().enumerate().map(|(index, (ident, typ))| {
-        quote! {
-            let #ident = <#typ>::from_argument(           // Perform conversion, assuming field impls FromKatcpArgument
-                msg.arguments()
-                    .get(#index)                          // Get the index associated with this field
-                    .ok_or(KatcpError::MissingArgument)?, // Ensure it exists
-            )?;
-        }
-    });
-    // The serde methods themselves
-    let names: Vec<Ident> = fields.iter().map(|e| e.clone().0).collect();
+/// Statements checking that decoding used up all of the `arguments`, unless extra arguments are allowed
+fn extra_arguments_check(allow_extra: bool) -> proc_macro2::TokenStream {
+    if allow_extra {
+        return quote! {};
+    }
     quote! {
-        fn #fn_to_message_args(&self) -> Result<(MessageKind, Vec<String>),KatcpError> {
-            if let Self::#kind {
-                #(#names),*
-            } = self {
-                #(let #names = #names.to_argument();)* // Assume field impls ToKatcpArgument
-                Ok((MessageKind::#kind, vec![#(#names),*]))
-            } else {
-                Err(KatcpError::BadArgument)
-            }
-        }
-        fn #fn_to_variant(msg: &Message) -> Result<Self,KatcpError> {
-            #(#arg_parses)*
-            Ok(Self::#kind{ #(#names),* })
-        }
+        arguments.finish()?;
     }
 }
 
-fn generate_unnamed_serde(variant: &Variant, ty: Vec<Type>) -> proc_macro2::TokenStream {
+/// The arm of the match encoding the fields of `variant` and the function decoding them
+struct VariantSerde {
+    encode_arm: proc_macro2::TokenStream,
+    decode_fn: proc_macro2::TokenStream,
+}
+
+fn generate_variant_serde(
+    variant: &Variant,
+    fields: &[ArgumentField],
+    allow_extra: bool,
+) -> VariantSerde {
     let kind = variant.ident.to_owned();
-    // Two function names
+    let extra_arguments_check = extra_arguments_check(allow_extra);
     let fn_to_variant = format_ident!(
         "to_{}_variant",
         variant.ident.to_owned().to_string().to_lowercase()
     );
-    let fn_to_message_args = format_ident!(
-        "to_{}_message_args",
-        variant.ident.to_owned().to_string().to_lowercase()
-    );
-    if ty.is_empty() {
-        quote! {
-             fn #fn_to_message_args(&self) -> Result<(MessageKind, Vec<String>), KatcpError> {
-                 Ok((MessageKind::#kind, Vec::<String>::new()))
-             }
-             fn #fn_to_variant(msg: &Message) -> Result<Self, KatcpError> {
-                 Ok(Self::#kind)
-             }
-        }
-    } else if ty.len() == 1 {
-        let ty = ty.get(0).unwrap();
-        quote! {
-            fn #fn_to_message_args(&self) -> Result<(MessageKind, Vec<String>), KatcpError> {
-                if let Self::#kind (field) = self {
-                    Ok((MessageKind::#kind, field.to_arguments()))
-                } else {
-                    Err(KatcpError::BadArgument)
-                }
+    let patterns: Vec<_> = fields.iter().map(ArgumentField::pattern).collect();
+    let encode_fields = encode_argument_fields(fields);
+    let decode_fields = decode_argument_fields(fields);
+    VariantSerde {
+        encode_arm: quote! {
+            Self::#kind { #(#patterns),* } => {
+                #encode_fields
             }
-            fn #fn_to_variant(msg: &Message) -> Result<Self, KatcpError> {
-                // This seems bad
-                let mut arg_iter = msg.arguments().into_iter();
-                Ok(Self::#kind(<#ty>::from_arguments(&mut arg_iter)?))
+        },
+        decode_fn: quote! {
+            fn #fn_to_variant<__A: AsRef<[u8]>>(
+                arguments: &mut ArgumentDecoder<'_, __A>,
+            ) -> Result<Self, KatcpError> {
+                #decode_fields
+                #extra_arguments_check
+                Ok(Self::#kind { #(#patterns),* })
             }
-        }
-    } else {
-        panic!("Unanmed variants must have at most 1 field")
+        },
     }
 }
 
-// This requires that arguments implement Display
-fn generate_serde(variant: &Option<Variant>) -> proc_macro2::TokenStream {
-    // Check if variant is None, return empty TokenStream if it is
+fn generate_serde(
+    variant: &Option<Variant>,
+    allow_extra: bool,
+) -> syn::Result<Option<VariantSerde>> {
+    // Check if variant is None, return nothing if it is
     let variant = if let Some(v) = variant {
         v
     } else {
-        return quote! {};
+        return Ok(None);
     };
-    // Grab fields
-    let fields_named = get_named_field_types_and_names(variant);
-    let fields_unnamed = get_unnamed_field_types(variant);
-    // Check to make sure our fields are homogeneous
-    if fields_named.is_some() && fields_unnamed.is_some() {
-        panic!("Variant can only have named or unnamed fields, not both");
-    }
+    let allow_extra =
+        allow_extra || katcp_attributes(&variant.attrs, &["allow_extra"])?.allow_extra;
     // Check fields and dispatch
-    if let Some(fields) = fields_named {
-        generate_named_serde(variant, fields)
-    } else if let Some(fields) = fields_unnamed {
-        generate_unnamed_serde(variant, fields)
-    } else {
-        generate_unnamed_serde(variant, Vec::<Type>::new())
-    }
+    let fields = match &variant.fields {
+        Fields::Named(_) => get_named_fields(&variant.fields)?,
+        Fields::Unnamed(unnamed) => get_unnamed_field(&variant.fields, unnamed)?,
+        Fields::Unit => Vec::new(),
+    };
+    Ok(Some(generate_variant_serde(variant, &fields, allow_extra)))
 }
 
 fn generate_try_from(
     message_name: &Ident,
+    message_str: &str,
     sorted_variants: &(Option<Variant>, Option<Variant>, Option<Variant>),
 ) -> proc_macro2::TokenStream {
-    let message_str = message_name.to_string().to_case(Case::Kebab);
+    // Peers can send any kind of message, so one we don't have is an error rather than a panic
+    let unsupported = quote! {
+        Err(KatcpError::UnsupportedKind { name: #message_str, kind: RawMessage::kind(message) })
+    };
     let request_fn = sorted_variants.0.as_ref().map_or(
-        quote! {unimplemented!()},
-        |_| quote! {#message_name::to_request_variant(&message)},
+        unsupported.clone(),
+        |_| quote! {#message_name::to_request_variant(&mut arguments)},
     );
     let reply_fn = sorted_variants.1.as_ref().map_or(
-        quote! {unimplemented!()},
-        |_| quote! {#message_name::to_reply_variant(&message)},
+        unsupported.clone(),
+        |_| quote! {#message_name::to_reply_variant(&mut arguments)},
     );
     let inform_fn = sorted_variants.2.as_ref().map_or(
-        quote! {unimplemented!()},
-        |_| quote! {#message_name::to_inform_variant(&message)},
+        unsupported.clone(),
+        |_| quote! {#message_name::to_inform_variant(&mut arguments)},
     );
     quote! {
-        impl TryFrom<Message> for #message_name {
-            type Error = KatcpError;
-            fn try_from(message: Message) -> Result<Self,Self::Error> {
-                if message.name() != #message_str {
-                    return Err(KatcpError::IncorrectType);
+        impl #message_name {
+            /// Decodes any of the raw message types in place, from a peer speaking `dialect`
+            fn from_raw_message<M: RawMessage>(
+                message: &M,
+                dialect: ProtocolDialect,
+            ) -> Result<Self, KatcpError> {
+                if RawMessage::name(message) != #message_str {
+                    return Err(KatcpError::IncorrectType {
+                        expected: #message_str,
+                        found: ::katcp::__alloc::borrow::ToOwned::to_owned(
+                            RawMessage::name(message),
+                        ),
+                    });
                 }
-                match message.kind() {
+                #[allow(unused_mut, unused_variables)]
+                let mut arguments =
+                    ArgumentDecoder::with_dialect(RawMessage::arguments(message), dialect);
+                let result = match RawMessage::kind(message) {
                     MessageKind::Request => #request_fn,
                     MessageKind::Reply => #reply_fn,
                     MessageKind::Inform => #inform_fn,
-                }
+                };
+                result.map_err(|e| e.in_message(#message_str))
+            }
+        }
+        impl<'a> TryFrom<MessageRef<'a>> for #message_name {
+            type Error = KatcpError;
+            fn try_from(message: MessageRef<'a>) -> Result<Self,Self::Error> {
+                Self::from_raw_message(&message, ProtocolDialect::V5)
+            }
+        }
+        impl TryFrom<Message> for #message_name {
+            type Error = KatcpError;
+            fn try_from(message: Message) -> Result<Self,Self::Error> {
+                Self::from_raw_message(&message, ProtocolDialect::V5)
+            }
+        }
+        impl<'a> TryFrom<MessageBytes<'a>> for #message_name {
+            type Error = KatcpError;
+            fn try_from(message: MessageBytes<'a>) -> Result<Self,Self::Error> {
+                Self::from_raw_message(&message, ProtocolDialect::V5)
             }
         }
     }
@@ -194,118 +412,265 @@ fn generate_try_from(
 fn generate_katcp_message_impl(
     message_name: &Ident,
     sorted_variants: &(Option<Variant>, Option<Variant>, Option<Variant>),
+    serdes: &[&VariantSerde],
 ) -> proc_macro2::TokenStream {
-    let message_str = message_name.to_string().to_case(Case::Kebab);
-    let request_fn = sorted_variants.0.as_ref().map_or(quote! {}, |_| {
-        quote! {
-            v @ Self::Request { .. } => v.to_request_message_args()?,
-        }
-    });
-    let reply_fn = sorted_variants.1.as_ref().map_or(quote! {}, |_| {
-        quote! {
-            v @ Self::Reply { .. } => v.to_reply_message_args()?,
-        }
-    });
-    let inform_fn = sorted_variants.2.as_ref().map_or(quote! {}, |_| {
-        quote! {
-            v @ Self::Inform { .. } => v.to_inform_message_args()?,
-        }
-    });
+    let kinds = [&sorted_variants.0, &sorted_variants.1, &sorted_variants.2]
+        .into_iter()
+        .flatten()
+        .map(|variant| &variant.ident);
+    let encode_arms = serdes.iter().map(|serde| &serde.encode_arm);
     quote! {
+        impl #message_name {
+            fn message_kind(&self) -> MessageKind {
+                match self {
+                    #(Self::#kinds { .. } => MessageKind::#kinds,)*
+                }
+            }
+
+            fn encode_message_arguments<__E>(
+                &self,
+                encoder: &mut ArgumentEncoder<'_, __E>,
+            ) -> Result<(), __E> {
+                match self {
+                    #(#encode_arms)*
+                }
+                Ok(())
+            }
+        }
         impl KatcpMessage for #message_name {
-            fn to_message(&self, id: Option<u32>) -> MessageResult {
-                let (kind, args) = match self {
-                    #request_fn
-                    #reply_fn
-                    #inform_fn
-                };
-                // Safety: all strings have been escaped and core types have been
-                // serialized according to the spec, so we shouldn't fail any parser
-                // rules here, implying this is ok
-                Ok(unsafe { Message::new_unchecked(kind, #message_str, id, args) } )
+            fn to_message(&self, id: Option<MessageId>) -> MessageResult {
+                self.to_dialect_message(id, ProtocolDialect::V5)
             }
+            fn to_dialect_message(
+                &self,
+                id: Option<MessageId>,
+                dialect: ProtocolDialect,
+            ) -> MessageResult {
+                __encode_message(self.message_kind(), Self::NAME, id, dialect, |encoder| {
+                    self.encode_message_arguments(encoder)
+                })
+            }
+            fn from_dialect_message(
+                message: Message,
+                dialect: ProtocolDialect,
+            ) -> Result<Self, KatcpError> {
+                Self::from_raw_message(&message, dialect)
+            }
+            __katcp_write_to!();
         }
     }
 }
 
-#[proc_macro_derive(KatcpMessage)]
+#[proc_macro_derive(KatcpMessage, attributes(katcp))]
 /// This derive macro creates serde methods for a decorated enum that has any of the variants `Request`, `Reply`, and `Inform`.
 /// The variants must have named data associated with them and every field of that data must impl `ToKatcpArgument` and `FromKatcpArgument`.
-/// The message name that is generated is a kebab-case version of the enum name.
+/// The exception is the last field, which can be marked `#[katcp(flatten)]` to take all of the remaining arguments with
+/// `ToKatcpArguments` and `FromKatcpArguments` (e.g. a `Vec`, tuple or array).
+/// The message name that is generated is a kebab-case version of the enum name, unless it's given with `#[katcp(name = "...")]`.
+/// `Option` fields marked `#[katcp(skip_if_none)]` leave out their argument when they're `None` (rather than sending `\@`),
+/// so they must come after all of the other fields.
+/// Decoding a message with more arguments than the variant takes is an error, unless the enum or variant is marked
+/// `#[katcp(allow_extra)]`, in which case the surplus arguments are ignored.
+/// The enum also gets a `NAME` constant with the message name and, if it has a `Request` variant, a `help()` function
+/// returning its `#help` inform. The description is the request's doc comment (or the enum's if it has none) as plain
+/// text, without the backticks and link targets of its markdown, unless it's given with `#[katcp(usage = "...")]`.
 pub fn derive_katcp(tokens: TokenStream) -> TokenStream {
     // We need to parse out the name of the enum,
     // the three variants(inform, reply, request)
     // and the fields of those variants
     let input = parse_macro_input!(tokens as DeriveInput);
+    expand_katcp_message(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// The variants of the enum being derived on, as the derives can't be used on anything else
+fn enum_variants(data: syn::Data, derive: &str) -> syn::Result<Vec<Variant>> {
+    let message = format!("{} can only be derived on enums", derive);
+    match data {
+        syn::Data::Enum(DataEnum { variants, .. }) => Ok(variants.into_iter().collect()),
+        syn::Data::Struct(data) => Err(syn::Error::new_spanned(data.struct_token, message)),
+        syn::Data::Union(data) => Err(syn::Error::new_spanned(data.union_token, message)),
+    }
+}
+
+fn expand_katcp_message(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
     let message_name = input.ident;
-    let variants: Vec<_> = match input.data {
-        syn::Data::Enum(DataEnum { variants, .. }) => variants.into_iter().collect(),
-        _ => panic!("KatcpMessage can only be derived on Enums"),
+    let attributes = katcp_attributes(&input.attrs, &["name", "allow_extra", "usage"])?;
+    let message_str = match attributes.name {
+        Some(name) if !is_valid_name(&name.value()) => {
+            return Err(syn::Error::new_spanned(
+                &name,
+                format!(
+                    "`{}` isn't a valid katcp message name, which must be a letter followed by \
+                     letters, digits and dashes",
+                    name.value()
+                ),
+            ))
+        }
+        Some(name) => name.value(),
+        None => message_name.to_string().to_case(Case::Kebab),
     };
+    let variants = enum_variants(input.data, "KatcpMessage")?;
     // Collect the three variants
-    let sorted_variants = sort_variants(variants);
+    let sorted_variants = sort_variants(&message_name, variants)?;
 
-    // Serialize into args fns
-    let serde_req = generate_serde(&sorted_variants.0);
-    let serde_reply = generate_serde(&sorted_variants.1);
-    let serde_inform = generate_serde(&sorted_variants.2);
+    // Encoding match arms and decoding fns
+    let serdes = [&sorted_variants.0, &sorted_variants.1, &sorted_variants.2]
+        .into_iter()
+        .map(|variant| generate_serde(variant, attributes.allow_extra))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let serdes: Vec<&VariantSerde> = serdes.iter().flatten().collect();
+    let decode_fns = serdes.iter().map(|serde| &serde.decode_fn);
+
+    // Help for requests comes from the most specific description we have
+    let help = sorted_variants.0.as_ref().map_or(quote! {}, |request| {
+        let description = match &attributes.usage {
+            Some(usage) => usage.value(),
+            None => match doc_comment(&request.attrs) {
+                doc if doc.is_empty() => strip_markup(&doc_comment(&input.attrs)),
+                doc => strip_markup(&doc),
+            },
+        };
+        quote! {
+            /// The `#help` inform describing this request
+            // `Help::help()` is the help for the help request
+            #[allow(clippy::self_named_constructors)]
+            pub fn help() -> ::katcp::messages::core::Help {
+                ::katcp::messages::core::Help::Inform {
+                    name: ::katcp::__alloc::borrow::ToOwned::to_owned(#message_str),
+                    description: ::katcp::__alloc::borrow::ToOwned::to_owned(#description),
+                }
+            }
+        }
+    });
 
     // TryFrom<Message> Block
-    let try_from_message = generate_try_from(&message_name, &sorted_variants);
+    let try_from_message = generate_try_from(&message_name, &message_str, &sorted_variants);
 
     // impl KatcpMessage Block
-    let katcp_message_impl = generate_katcp_message_impl(&message_name, &sorted_variants);
+    let katcp_message_impl = generate_katcp_message_impl(&message_name, &sorted_variants, &serdes);
 
-    let generated = quote! {
+    Ok(quote! {
         #try_from_message
         #katcp_message_impl
         impl TryFrom<&str> for #message_name {
             type Error = KatcpError;
             fn try_from(s: &str) -> Result<Self, Self::Error> {
-                let message: Message = s.try_into()?;
+                let message: MessageRef = s.try_into()?;
                 message.try_into()
             }
         }
         impl #message_name {
-            #serde_req
-            #serde_reply
-            #serde_inform
+            /// The name of this message
+            pub const NAME: &'static str = #message_str;
+            #help
+            #(#decode_fns)*
         }
-    };
-    // Return generated code
-    TokenStream::from(generated)
+    })
+}
+
+/// The strings that stand for each of `variants` on the wire: the kebab-cased variant name unless it's given with
+/// `#[katcp(rename = "...")]`. These are sent as is, so `what` they are is checked for being unique and not needing
+/// escaping, adding to `errors`.
+fn variant_values(
+    variants: &[Variant],
+    what: &str,
+    errors: &mut Option<syn::Error>,
+) -> Vec<String> {
+    let mut values: Vec<String> = Vec::new();
+    for variant in variants {
+        let rename = match katcp_attributes(&variant.attrs, &["rename"]) {
+            Ok(attributes) => attributes.rename,
+            Err(e) => {
+                push_error(errors, e);
+                None
+            }
+        };
+        let value = rename.as_ref().map_or_else(
+            || variant.ident.to_string().to_case(Case::Kebab),
+            LitStr::value,
+        );
+        // Point at the rename if there is one, otherwise the variant it came from
+        let error = |message: String| match &rename {
+            Some(rename) => syn::Error::new_spanned(rename, message),
+            None => syn::Error::new_spanned(&variant.ident, message),
+        };
+        let needs_escaping = ['\\', ' ', '\0', '\n', '\r', '\x1b', '\t'];
+        if value.is_empty() || value.contains(&needs_escaping[..]) {
+            push_error(
+                errors,
+                error(format!(
+                    "{:?} can't be used as a {}, as it's empty or would need escaping",
+                    value, what
+                )),
+            );
+        } else if values.contains(&value) {
+            push_error(
+                errors,
+                error(format!("the {} {:?} is used more than once", what, value)),
+            );
+        }
+        values.push(value);
+    }
+    values
 }
 
-#[proc_macro_derive(KatcpDiscrete)]
+#[proc_macro_derive(KatcpDiscrete, attributes(katcp))]
 /// This derive macro decorates an enum to implement ToKatcpArgument and FromKatcpArgument for use with the [`KatcpMessage`] macro.
 /// This will create a bidirectional mapping between the variant names and a kebab-cased string of the variant (as per the spec).
+/// A variant's value can be given explicitly with `#[katcp(rename = "...")]`.
 pub fn derive_katcp_discrete(tokens: TokenStream) -> TokenStream {
     let input = parse_macro_input!(tokens as DeriveInput);
+    expand_katcp_discrete(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand_katcp_discrete(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
     let enum_name = input.ident;
-    let variants: Vec<_> = match input.data {
-        syn::Data::Enum(DataEnum { variants, .. }) => variants.into_iter().collect(),
-        _ => panic!("KatcpDiscrete can only be derived on Enums"),
-    };
-    let to_str_pairs = variants.iter().map(|variant| {
+    katcp_attributes(&input.attrs, &[])?;
+    let variants = enum_variants(input.data, "KatcpDiscrete")?;
+    if variants.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &enum_name,
+            "KatcpDiscrete needs at least one variant",
+        ));
+    }
+    let mut errors = None;
+    for variant in &variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            push_error(
+                &mut errors,
+                syn::Error::new_spanned(
+                    &variant.fields,
+                    "KatcpDiscrete variants can't have any fields",
+                ),
+            );
+        }
+    }
+    let values = variant_values(&variants, "discrete value", &mut errors);
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+    let to_str_pairs = variants.iter().zip(&values).map(|(variant, value)| {
         let ident = variant.ident.clone();
-        let ident_lowercase = variant.ident.to_string().to_case(Case::Kebab);
         quote! {
-            #enum_name::#ident => #ident_lowercase
+            #enum_name::#ident => #value
         }
     });
-    let from_str_pairs = variants.iter().map(|variant| {
+    let from_str_pairs = variants.iter().zip(&values).map(|(variant, value)| {
         let ident = variant.ident.clone();
-        let ident_lowercase = variant.ident.to_string().to_case(Case::Kebab);
         quote! {
-            #ident_lowercase => #enum_name::#ident
+            #value => #enum_name::#ident
         }
     });
-    let generated = quote! {
+    Ok(quote! {
         impl ToKatcpArgument for #enum_name {
-            fn to_argument(&self) -> String {
-                match self {
+            fn to_argument(&self) -> ::katcp::__alloc::string::String {
+                ::katcp::__alloc::borrow::ToOwned::to_owned(match self {
                     #(#to_str_pairs),*
-                }.to_owned()
+                })
             }
         }
         impl FromKatcpArgument for #enum_name {
@@ -313,11 +678,320 @@ pub fn derive_katcp_discrete(tokens: TokenStream) -> TokenStream {
             fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
                 let level = match s.as_ref() {
                     #(#from_str_pairs),*,
-                    _ =>  return Err(KatcpError::BadArgument),
+                    _ =>  return Err(KatcpError::bad_argument(s.as_ref())),
                 };
                 Ok(level)
             }
         }
+    })
+}
+
+/// A field of a struct or enum variant deriving `KatcpArguments`, or of a message variant
+struct ArgumentField {
+    /// How to get at the field, `name` or `0`
+    member: Member,
+    /// The local variable the field is bound to, which can't clash with the ones in the generated code
+    binding: Ident,
+    /// The name of the field in errors, if it has one worth giving
+    name: Option<String>,
+    ty: Type,
+    /// Whether the field takes its arguments with To/FromKatcpArguments rather than a single one
+    flatten: bool,
+    skip_if_none: bool,
+    /// What to point at in errors about the field
+    span: proc_macro2::TokenStream,
+}
+
+impl ArgumentField {
+    /// The field in a pattern or constructor, binding it to (or taking it from) `binding`
+    fn pattern(&self) -> proc_macro2::TokenStream {
+        let ArgumentField {
+            member, binding, ..
+        } = self;
+        quote! { #member: #binding }
+    }
+}
+
+/// Collects and validates the fields of a struct or enum variant deriving `KatcpArguments`
+fn argument_fields(fields: &Fields) -> syn::Result<Vec<ArgumentField>> {
+    let mut parsed = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let attributes = katcp_attributes(&field.attrs, &["flatten", "skip_if_none"])?;
+        let (member, name, span) = match &field.ident {
+            Some(ident) => (
+                Member::Named(ident.clone()),
+                ident.unraw().to_string(),
+                quote! { #ident },
+            ),
+            None => {
+                let ty = &field.ty;
+                (
+                    Member::Unnamed(index.into()),
+                    index.to_string(),
+                    quote! { #ty },
+                )
+            }
+        };
+        if attributes.skip_if_none && !is_option(&field.ty) {
+            return Err(syn::Error::new_spanned(
+                &field.ty,
+                "`#[katcp(skip_if_none)]` can only be used on `Option` fields",
+            ));
+        }
+        if attributes.flatten && attributes.skip_if_none && index + 1 != fields.len() {
+            return Err(syn::Error::new_spanned(
+                &span,
+                "`#[katcp(flatten, skip_if_none)]` can only be used on the last field, as leaving it out would be \
+                 ambiguous otherwise",
+            ));
+        }
+        // The only field of a newtype is better described by the field the whole group is in
+        let newtype = field.ident.is_none() && fields.len() == 1;
+        parsed.push(ArgumentField {
+            member,
+            binding: format_ident!("field_{}", name),
+            name: if newtype { None } else { Some(name) },
+            ty: field.ty.clone(),
+            flatten: attributes.flatten,
+            skip_if_none: attributes.skip_if_none,
+            span,
+        });
+    }
+    // Leaving out an argument would shift every argument after it
+    if let Some(first) = parsed.iter().position(|f| f.skip_if_none) {
+        if let Some(field) = parsed[first..].iter().find(|f| !f.skip_if_none) {
+            return Err(syn::Error::new_spanned(
+                &field.span,
+                "fields with `#[katcp(skip_if_none)]` must come after all of the other fields",
+            ));
+        }
+    }
+    Ok(parsed)
+}
+
+/// Statements decoding each of `fields` from the `arguments` decoder into their bindings
+fn decode_argument_fields(fields: &[ArgumentField]) -> proc_macro2::TokenStream {
+    let decodes = fields.iter().map(|field| {
+        let ArgumentField { binding, ty, .. } = field;
+        let in_field = field
+            .name
+            .as_ref()
+            .map(|name| quote! { .map_err(|e| e.in_field(#name)) });
+        match (field.flatten, field.skip_if_none) {
+            (false, false) => quote! {
+                let #binding = arguments.decode::<#ty>()#in_field?;
+            },
+            // A missing argument is a `None`
+            (false, true) => quote! {
+                let #binding = match arguments.remaining() {
+                    0 => None,
+                    _ => arguments.decode::<#ty>()#in_field?,
+                };
+            },
+            (true, false) => quote! {
+                let #binding = arguments.decode_group::<#ty>()#in_field?;
+            },
+            (true, true) => {
+                // We've already checked that this is an `Option`
+                let inner = option_inner(ty).unwrap_or(ty);
+                quote! {
+                    let #binding = match arguments.remaining() {
+                        0 => None,
+                        _ => Some(arguments.decode_group::<#inner>()#in_field?),
+                    };
+                }
+            }
+        }
+    });
+    quote! { #(#decodes)* }
+}
+
+/// Statements encoding each of `fields` from their bindings into `encoder`
+fn encode_argument_fields(fields: &[ArgumentField]) -> proc_macro2::TokenStream {
+    let encode = |field: &ArgumentField, value: &dyn quote::ToTokens| {
+        if field.flatten {
+            quote! { encoder.encode_group(#value)?; }
+        } else {
+            quote! { encoder.encode(#value)?; }
+        }
     };
-    TokenStream::from(generated)
+    let encodes = fields
+        .iter()
+        .filter(|field| !field.skip_if_none)
+        .map(|field| encode(field, &field.binding));
+    let optional: Vec<&ArgumentField> = fields.iter().filter(|field| field.skip_if_none).collect();
+    let optional_args = if optional.is_empty() {
+        quote! {}
+    } else {
+        // Only the trailing `None`s can be left out, any before a `Some` are sent as usual
+        let bindings = optional.iter().map(|field| &field.binding);
+        let encodes = optional.iter().enumerate().map(|(index, field)| {
+            let binding = &field.binding;
+            let encode = encode(field, &quote! { value });
+            quote! {
+                if #index < present {
+                    match #binding {
+                        Some(value) => { #encode }
+                        None => encoder.push(br"\@")?,
+                    }
+                }
+            }
+        });
+        quote! {
+            let present = [#(#bindings.is_some()),*]
+                .iter()
+                .rposition(|some| *some)
+                .map_or(0, |i| i + 1);
+            #(#encodes)*
+        }
+    };
+    quote! {
+        #(#encodes)*
+        #optional_args
+    }
+}
+
+#[proc_macro_derive(KatcpArguments, attributes(katcp))]
+/// This derive macro implements ToKatcpArguments and FromKatcpArguments for a group of arguments, so it can be used
+/// inside an unnamed [`KatcpMessage`] variant or as a flattened field of another group.
+/// On a struct, every field is a single argument with To/FromKatcpArgument, in order. Fields marked
+/// `#[katcp(flatten)]` take their arguments with To/FromKatcpArguments instead (e.g. a `Vec`, tuple or array). Trailing `Option` fields marked `#[katcp(skip_if_none)]` leave out their arguments when they're
+/// `None`. `#[katcp(validate = "path")]` calls `path(&self) -> Result<(), KatcpError>` on every decoded struct, for
+/// rules that involve more than one field.
+/// On an enum, the first argument picks the variant with the kebab-cased variant name (or `#[katcp(rename = "...")]`),
+/// followed by the variant's fields as for a struct. `#[katcp(tag = "...")]` names that first argument in errors.
+pub fn derive_katcp_arguments(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as DeriveInput);
+    expand_katcp_arguments(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand_katcp_arguments(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = input.ident;
+    let (encode, decode) = match input.data {
+        syn::Data::Struct(data) => {
+            let validate = katcp_attributes(&input.attrs, &["validate"])?
+                .validate
+                .map(|path| path.parse::<syn::ExprPath>())
+                .transpose()?
+                .map(|path| quote! { #path(&decoded)?; });
+            let fields = argument_fields(&data.fields)?;
+            let patterns = fields.iter().map(ArgumentField::pattern);
+            let encode_fields = encode_argument_fields(&fields);
+            let decode_fields = decode_argument_fields(&fields);
+            let pattern = quote! { Self { #(#patterns),* } };
+            (
+                quote! {
+                    let #pattern = self;
+                    #encode_fields
+                },
+                quote! {
+                    #decode_fields
+                    let decoded = #pattern;
+                    #validate
+                    Ok(decoded)
+                },
+            )
+        }
+        syn::Data::Enum(data) => {
+            let tag = katcp_attributes(&input.attrs, &["tag"])?
+                .tag
+                .map_or_else(|| "tag".to_owned(), |tag| tag.value());
+            let variants: Vec<_> = data.variants.into_iter().collect();
+            if variants.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    &name,
+                    "KatcpArguments needs at least one variant",
+                ));
+            }
+            let mut errors = None;
+            let values = variant_values(&variants, "tag", &mut errors);
+            let mut encode_arms = Vec::new();
+            let mut decode_arms = Vec::new();
+            for (variant, value) in variants.iter().zip(&values) {
+                let fields = match argument_fields(&variant.fields) {
+                    Ok(fields) => fields,
+                    Err(e) => {
+                        push_error(&mut errors, e);
+                        continue;
+                    }
+                };
+                let ident = &variant.ident;
+                let patterns = fields.iter().map(ArgumentField::pattern);
+                let encode_fields = encode_argument_fields(&fields);
+                let decode_fields = decode_argument_fields(&fields);
+                let pattern = quote! { Self::#ident { #(#patterns),* } };
+                let value = LitByteStr::new(value.as_bytes(), ident.span());
+                encode_arms.push(quote! {
+                    #pattern => {
+                        encoder.push(#value)?;
+                        #encode_fields
+                    }
+                });
+                decode_arms.push(quote! {
+                    #value => {
+                        #decode_fields
+                        #pattern
+                    }
+                });
+            }
+            if let Some(errors) = errors {
+                return Err(errors);
+            }
+            (
+                quote! {
+                    match self {
+                        #(#encode_arms)*
+                    }
+                },
+                quote! {
+                    let index = arguments.index();
+                    let tag = arguments.next().ok_or_else(|| {
+                        KatcpError::missing_argument().in_field(#tag).at_index(index)
+                    })?;
+                    // The tags never need escaping, so they can be compared as they are
+                    Ok(match tag {
+                        #(#decode_arms)*
+                        _ => {
+                            let tag = ::katcp::__alloc::string::String::from_utf8_lossy(tag);
+                            return Err(KatcpError::bad_argument(tag)
+                                .in_field(#tag)
+                                .at_index(index))
+                        }
+                    })
+                },
+            )
+        }
+        syn::Data::Union(data) => {
+            return Err(syn::Error::new_spanned(
+                data.union_token,
+                "KatcpArguments can only be derived on structs and enums",
+            ))
+        }
+    };
+    Ok(quote! {
+        impl ToKatcpArguments for #name {
+            fn to_arguments(&self) -> ::katcp::__alloc::vec::Vec<::katcp::__alloc::string::String> {
+                ArgumentEncoder::collect(|encoder| self.encode_arguments(encoder))
+            }
+
+            fn encode_arguments<__E>(
+                &self,
+                encoder: &mut ArgumentEncoder<'_, __E>,
+            ) -> Result<(), __E> {
+                #encode
+                Ok(())
+            }
+        }
+        impl FromKatcpArguments for #name {
+            type Err = KatcpError;
+            #[allow(unused_variables)]
+            fn from_arguments<__A: AsRef<[u8]>>(
+                arguments: &mut ArgumentDecoder<'_, __A>,
+            ) -> Result<Self, Self::Err> {
+                #decode
+            }
+        }
+    })
 }
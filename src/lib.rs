@@ -19,16 +19,55 @@
 //! Usually, you will interact with specific message types, these are organized in the same way they are presented in the spec, but will be reiteraeted here:
 //!
 //!
-//! |                         Core                         |                 Log                 |                       Sensors                       |                        Multi-Client                        |
-//! |------------------------------------------------------|-------------------------------------|-----------------------------------------------------|------------------------------------------------------------|
-//! |             [Halt](messages::core::Halt)             |      [Log](messages::log::Log)      |     [SensorList](messages::sensors::SensorList)     |      [ClientList](messages::multi_client::ClientList)      |
-//! |             [Help](messages::core::Help)             | [LogLevel](messages::log::LogLevel) | [SensorSampling](messages::sensors::SensorSampling) | [ClientConnected](messages::multi_client::ClientConnected) |
-//! |          [Restart](messages::core::Restart)          |                                     |    [SensorValue](messages::sensors::SensorValue)    |                                                            |
-//! |         [Watchdog](messages::core::Watchdog)         |                                     |   [SensorStatus](messages::sensors::SensorStatus)   |                                                            |
-//! |      [VersionList](messages::core::VersionList)      |                                     |                                                     |                                                            |
-//! |       [Disconnect](messages::core::Disconnect)       |                                     |                                                     |                                                            |
-//! |   [VersionConnect](messages::core::VersionConnect)   |                                     |                                                     |                                                            |
-//! | [InterfaceChanged](messages::core::InterfaceChanged) |                                     |                                                     |                                                            |
+//! |                           Core                           |                 Log                 |                            Sensors                            |                        Multi-Client                        |
+//! |----------------------------------------------------------|-------------------------------------|---------------------------------------------------------------|------------------------------------------------------------|
+//! |               [Halt](messages::core::Halt)               |      [Log](messages::log::Log)      |          [SensorList](messages::sensors::SensorList)          |      [ClientList](messages::multi_client::ClientList)      |
+//! |               [Help](messages::core::Help)               | [LogLevel](messages::log::LogLevel) |      [SensorSampling](messages::sensors::SensorSampling)      | [ClientConnected](messages::multi_client::ClientConnected) |
+//! |            [Restart](messages::core::Restart)            |                                     | [SensorSamplingClear](messages::sensors::SensorSamplingClear) |                                                            |
+//! |           [Watchdog](messages::core::Watchdog)           |                                     |         [SensorValue](messages::sensors::SensorValue)         |                                                            |
+//! |        [VersionList](messages::core::VersionList)        |                                     |        [SensorStatus](messages::sensors::SensorStatus)        |                                                            |
+//! |         [Disconnect](messages::core::Disconnect)         |                                     |                                                               |                                                            |
+//! |     [VersionConnect](messages::core::VersionConnect)     |                                     |                                                               |                                                            |
+//! |            [Version](messages::core::Version)            |                                     |                                                               |                                                            |
+//! |         [BuildState](messages::core::BuildState)         |                                     |                                                               |                                                            |
+//! |   [InterfaceChanged](messages::core::InterfaceChanged)   |                                     |                                                               |                                                            |
+//! | [RequestTimeoutHint](messages::core::RequestTimeoutHint) |                                     |                                                               |                                                            |
+//!
+//! The message types follow version 5 of the spec. To talk to KATCP v4 devices, which send millisecond timestamps,
+//! encode and decode them with a [ProtocolDialect](messages::common::ProtocolDialect).
+//!
+//! ## Features
+//!
+//! - `std` (default): implementations of `std::error::Error` and serialization into `std::io::Write`rs. Without it,
+//!   the crate is `no_std` and only needs `alloc`, so the parser and message types can run on embedded targets.
+//! - `serde`: `Serialize` and `Deserialize` for [Message](protocol::Message) and all of the message types.
+//! - `arbitrary`: [`Arbitrary`](https://docs.rs/arbitrary) for [Message](protocol::Message) and all of the message
+//!   types, for fuzzing and property testing. Generated values are always valid and round-trip through katcp
+//!   unchanged. The fuzz targets in `fuzz/` (run with `cargo fuzz run message` or `cargo fuzz run roundtrip`) use
+//!   this.
+//!
+//! ### Serde representation
+//!
+//! The shape of the serialized data is considered part of the public API and follows a few rules:
+//!
+//! - [Message](protocol::Message)s are `{"kind": "request", "name": "set-rate", "id": 5, "arguments": ["4.1"]}` where
+//!   `id` may be `null` and the arguments are exactly as they appear on the wire (i.e. escaped). Deserialization
+//!   validates the message like [Message::new](protocol::Message::new).
+//! - Enums with only unit variants ([Status](messages::sensors::Status), [Level](messages::log::Level),
+//!   [RetCode](messages::common::RetCode), etc.) are their kebab-case names, e.g. `"warn"`.
+//! - All other enums use serde's default externally tagged representation with the rust names, e.g.
+//!   `{"Inform": {"message": "bye"}}` or `"Request"`, and structs are maps of their fields.
+//! - [KatcpTimestamp](messages::common::KatcpTimestamp)s are RFC 3339 strings in UTC, e.g. `"2022-07-11T00:40:00.500Z"`.
+//! - [KatcpAddress](messages::common::KatcpAddress)es are their katcp argument, e.g. `"192.168.1.2:7147"`.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+// So the derived code can name `::katcp` paths inside this crate too
+extern crate self as katcp;
+
+// The derive macros name `alloc`'s types through this, as they can't assume they're in scope without `std`
+#[doc(hidden)]
+pub extern crate alloc as __alloc;
 
 pub mod messages;
 pub mod prelude;
@@ -1,20 +1,152 @@
 //! Common message types and traits
-use std::{
-    fmt::Display,
-    net::{IpAddr, SocketAddr},
+use alloc::{
+    borrow::ToOwned,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
 };
+use core::{convert::Infallible, fmt::Display, time::Duration};
+#[cfg(feature = "std")]
+use std::io;
 
 use chrono::{DateTime, TimeZone, Utc};
-use katcp_derive::KatcpDiscrete;
+use katcp_derive::{KatcpArguments, KatcpDiscrete};
+use no_std_net::{IpAddr, SocketAddr};
 
+#[cfg(feature = "std")]
+use crate::protocol::serialize_header;
 use crate::{
-    protocol::{KatcpError, Message, MessageResult},
-    utils::{escape, unescape},
+    protocol::{KatcpError, Message, MessageId, MessageKind, MessageResult},
+    utils::{escape, escape_bytes, unescape, unescape_bytes},
 };
 
 /// The trait that specific katcp messages should implement
 pub trait KatcpMessage: TryFrom<Message> {
-    fn to_message(&self, id: Option<u32>) -> MessageResult;
+    fn to_message(&self, id: Option<MessageId>) -> MessageResult;
+
+    /// Like [`to_message`](Self::to_message), but with the arguments encoded for a peer speaking `dialect`. The
+    /// derived implementations encode every argument in `dialect`, this default is only right for messages with
+    /// nothing that differs between dialects.
+    fn to_dialect_message(&self, id: Option<MessageId>, dialect: ProtocolDialect) -> MessageResult {
+        let _ = dialect;
+        self.to_message(id)
+    }
+
+    /// Decodes a `message` from a peer speaking `dialect`. As with
+    /// [`to_dialect_message`](Self::to_dialect_message), the derived implementations decode every argument in
+    /// `dialect` and this default ignores it.
+    fn from_dialect_message(message: Message, dialect: ProtocolDialect) -> Result<Self, KatcpError>
+    where
+        Self: TryFrom<Message, Error = KatcpError>,
+    {
+        let _ = dialect;
+        Self::try_from(message)
+    }
+
+    /// Serializes the message straight into `writer`, writing exactly what `to_message(id)` would
+    #[cfg(feature = "std")]
+    fn write_to<W: io::Write + ?Sized>(
+        &self,
+        id: Option<MessageId>,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        self.write_dialect_to(id, ProtocolDialect::V5, writer)
+    }
+
+    /// Serializes the message straight into `writer` for a peer speaking `dialect`, writing exactly what
+    /// `to_dialect_message(id, dialect)` would. The derived implementations write each argument as it's encoded,
+    /// without building a [`Message`] first.
+    #[cfg(feature = "std")]
+    fn write_dialect_to<W: io::Write + ?Sized>(
+        &self,
+        id: Option<MessageId>,
+        dialect: ProtocolDialect,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        self.to_dialect_message(id, dialect)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .write_to(writer)
+    }
+}
+
+/// Implements [`KatcpMessage::write_dialect_to`] for the derive macro, as it can't see which features this crate was built with
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "std")]
+macro_rules! __katcp_write_to {
+    () => {
+        fn write_dialect_to<W: ::std::io::Write + ?Sized>(
+            &self,
+            id: Option<$crate::protocol::MessageId>,
+            dialect: $crate::messages::common::ProtocolDialect,
+            writer: &mut W,
+        ) -> ::std::io::Result<()> {
+            $crate::messages::common::__write_message(
+                writer,
+                self.message_kind(),
+                Self::NAME,
+                id,
+                dialect,
+                |encoder| self.encode_message_arguments(encoder),
+            )
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "std"))]
+macro_rules! __katcp_write_to {
+    ($($tokens:tt)*) => {};
+}
+
+/// Builds a message out of the arguments that `encode` gives in `dialect`, for the derive macro
+#[doc(hidden)]
+pub fn __encode_message(
+    kind: MessageKind,
+    name: &str,
+    id: Option<MessageId>,
+    dialect: ProtocolDialect,
+    encode: impl FnOnce(&mut ArgumentEncoder<'_, KatcpError>) -> Result<(), KatcpError>,
+) -> MessageResult {
+    let mut arguments: Vec<String> = Vec::new();
+    let mut sink = |argument: &[u8]| {
+        let argument = core::str::from_utf8(argument).map_err(|_| {
+            KatcpError::bad_argument(String::from_utf8_lossy(argument)).at_index(arguments.len())
+        })?;
+        arguments.push(argument.to_owned());
+        Ok(())
+    };
+    encode(&mut ArgumentEncoder::with_dialect(&mut sink, dialect))?;
+    // The arguments have already been serialized according to the spec
+    Ok(Message {
+        kind,
+        name: name.to_owned(),
+        id,
+        arguments,
+    })
+}
+
+/// Writes a message straight into `writer` as `encode` gives its arguments in `dialect`, for the derive macro
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub fn __write_message<W: io::Write + ?Sized>(
+    writer: &mut W,
+    kind: MessageKind,
+    name: &str,
+    id: Option<MessageId>,
+    dialect: ProtocolDialect,
+    encode: impl FnOnce(&mut ArgumentEncoder<'_, io::Error>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut put = |piece: &[u8]| writer.write_all(piece);
+    serialize_header(kind, name, id, &mut put)?;
+    let mut sink = |argument: &[u8]| {
+        put(b" ")?;
+        put(argument)
+    };
+    encode(&mut ArgumentEncoder::with_dialect(&mut sink, dialect))?;
+    put(b"\n")
 }
 
 /// Serializes the implemented type into an argument string
@@ -22,6 +154,20 @@ pub trait KatcpMessage: TryFrom<Message> {
 pub trait ToKatcpArgument {
     /// Create a katcp message argument (String) from a self
     fn to_argument(&self) -> String;
+
+    /// Create a katcp message argument as raw bytes from a self.
+    /// Only types that can hold data that isn't valid UTF-8 need to override this
+    fn to_argument_bytes(&self) -> Vec<u8> {
+        self.to_argument().into_bytes()
+    }
+
+    /// Create a katcp message argument as raw bytes for a peer speaking `dialect`, which is what the
+    /// [`ArgumentEncoder`] of a message uses. Only types that are sent differently between dialects need to
+    /// override this
+    fn to_dialect_argument_bytes(&self, dialect: ProtocolDialect) -> Vec<u8> {
+        let _ = dialect;
+        self.to_argument_bytes()
+    }
 }
 
 /// Deserializes an argument string into the implemented type
@@ -33,20 +179,257 @@ where
     type Err; // Not Error as to not clash with Self being an enum with an `Error` variant
     /// Create a self from a katcp message argument (String), potentially erroring
     fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err>;
+
+    /// Create a self from a katcp message argument as raw bytes, potentially erroring.
+    /// Only types that can hold data that isn't valid UTF-8 need to override this, the rest reject it with a
+    /// [`KatcpError`]
+    fn from_argument_bytes(b: impl AsRef<[u8]>) -> Result<Self, KatcpError>
+    where
+        KatcpError: From<Self::Err>,
+    {
+        Ok(Self::from_argument(utf8_argument(b.as_ref())?)?)
+    }
+
+    /// Create a self from the raw bytes of an argument from a peer speaking `dialect`, which is what the
+    /// [`ArgumentDecoder`] of a message uses. Only types that are sent differently between dialects need to
+    /// override this
+    fn from_dialect_argument_bytes(
+        b: impl AsRef<[u8]>,
+        dialect: ProtocolDialect,
+    ) -> Result<Self, KatcpError>
+    where
+        KatcpError: From<Self::Err>,
+    {
+        let _ = dialect;
+        Self::from_argument_bytes(b)
+    }
+}
+
+/// The argument `b` as a `str`, for the types that can only be decoded from UTF-8
+fn utf8_argument(b: &[u8]) -> Result<&str, KatcpError> {
+    core::str::from_utf8(b).map_err(|_| KatcpError::bad_argument(String::from_utf8_lossy(b)))
 }
 
 /// A trait for serializing more complex types that return the full argument vector
 pub trait ToKatcpArguments {
     fn to_arguments(&self) -> Vec<String>;
+
+    /// Hands each argument to `encoder` in turn, which is what the derived messages use to write themselves. The
+    /// default goes through `to_arguments`, so it's always in v5. Override this to avoid building the whole vector
+    /// and to encode in the [`ArgumentEncoder::dialect`].
+    fn encode_arguments<E>(&self, encoder: &mut ArgumentEncoder<'_, E>) -> Result<(), E> {
+        self.to_arguments()
+            .iter()
+            .try_for_each(|argument| encoder.push(argument.as_bytes()))
+    }
+}
+
+/// Passes the arguments of a message on one at a time as they're encoded, to wherever they're going (e.g. straight
+/// into a writer). This is what [`ToKatcpArguments::encode_arguments`] encodes into.
+///
+/// ```
+/// use katcp::prelude::*;
+///
+/// let mut line = String::new();
+/// let mut sink = |argument: &[u8]| {
+///     line.push(' ');
+///     line.push_str(core::str::from_utf8(argument).unwrap());
+///     Ok::<_, KatcpError>(())
+/// };
+/// let mut encoder = ArgumentEncoder::new(&mut sink);
+/// encoder.encode("a b").unwrap();
+/// encoder.encode_group(&[1.5f64, 2.0]).unwrap();
+/// assert_eq!(" a\\_b 1.5 2", line);
+/// ```
+pub struct ArgumentEncoder<'a, E> {
+    sink: &'a mut dyn FnMut(&[u8]) -> Result<(), E>,
+    dialect: ProtocolDialect,
 }
 
-/// A trait for deserializing more complex types that consume an iterator of arguments
+impl<'a, E> ArgumentEncoder<'a, E> {
+    /// An encoder handing each (escaped) argument to `sink`
+    pub fn new(sink: &'a mut dyn FnMut(&[u8]) -> Result<(), E>) -> Self {
+        Self::with_dialect(sink, ProtocolDialect::default())
+    }
+
+    /// An encoder handing each (escaped) argument to `sink`, encoded for a peer speaking `dialect`
+    pub fn with_dialect(
+        sink: &'a mut dyn FnMut(&[u8]) -> Result<(), E>,
+        dialect: ProtocolDialect,
+    ) -> Self {
+        Self { sink, dialect }
+    }
+
+    /// The dialect the arguments are encoded in
+    pub fn dialect(&self) -> ProtocolDialect {
+        self.dialect
+    }
+
+    /// Passes on an argument that has already been escaped
+    pub fn push(&mut self, argument: &[u8]) -> Result<(), E> {
+        (self.sink)(argument)
+    }
+
+    /// Encodes `value` as the next argument, with [`ToKatcpArgument::to_dialect_argument_bytes`] so nothing is lost
+    pub fn encode<T: ToKatcpArgument + ?Sized>(&mut self, value: &T) -> Result<(), E> {
+        self.push(&value.to_dialect_argument_bytes(self.dialect))
+    }
+
+    /// Encodes the arguments of `group` next
+    pub fn encode_group<T: ToKatcpArguments + ?Sized>(&mut self, group: &T) -> Result<(), E> {
+        group.encode_arguments(self)
+    }
+}
+
+impl ArgumentEncoder<'_, Infallible> {
+    /// Collects the arguments that `encode` gives, for implementing [`ToKatcpArguments::to_arguments`] in terms of
+    /// [`ToKatcpArguments::encode_arguments`]. As they're `String`s, bytes that aren't valid UTF-8 are replaced with
+    /// U+FFFD.
+    pub fn collect(
+        encode: impl FnOnce(&mut ArgumentEncoder<'_, Infallible>) -> Result<(), Infallible>,
+    ) -> Vec<String> {
+        let mut arguments = Vec::new();
+        let encoded = encode(&mut ArgumentEncoder::new(&mut |argument| {
+            arguments.push(String::from_utf8_lossy(argument).into_owned());
+            Ok(())
+        }));
+        match encoded {
+            Ok(()) => arguments,
+            Err(e) => match e {},
+        }
+    }
+}
+
+/// A trait for deserializing more complex types that consume their arguments from an [`ArgumentDecoder`]
 pub trait FromKatcpArguments
 where
     Self: Sized,
 {
     type Err;
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err>;
+    fn from_arguments<A: AsRef<[u8]>>(
+        arguments: &mut ArgumentDecoder<'_, A>,
+    ) -> Result<Self, Self::Err>;
+}
+
+/// Decodes the arguments of a message in order, straight from the (still escaped) arguments it borrows.
+///
+/// It keeps track of which argument it's up to, so errors say where they happened. As an iterator, it yields the
+/// raw arguments it hasn't decoded yet.
+///
+/// ```
+/// use katcp::prelude::*;
+///
+/// let message: MessageRef = "?set-gains 3 0.5 1.25".try_into().unwrap();
+/// let mut arguments = ArgumentDecoder::new(message.arguments());
+/// let channel: u32 = arguments.decode().unwrap();
+/// let gains: Vec<f64> = arguments.decode_group().unwrap();
+/// assert_eq!((3, vec![0.5, 1.25]), (channel, gains));
+/// assert!(arguments.finish().is_ok());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ArgumentDecoder<'a, A> {
+    arguments: &'a [A],
+    position: usize,
+    dialect: ProtocolDialect,
+}
+
+impl<'a, A: AsRef<[u8]>> ArgumentDecoder<'a, A> {
+    pub fn new(arguments: &'a [A]) -> Self {
+        Self::with_dialect(arguments, ProtocolDialect::default())
+    }
+
+    /// A decoder for the arguments from a peer speaking `dialect`
+    pub fn with_dialect(arguments: &'a [A], dialect: ProtocolDialect) -> Self {
+        Self {
+            arguments,
+            position: 0,
+            dialect,
+        }
+    }
+
+    /// The dialect the arguments are decoded from
+    pub fn dialect(&self) -> ProtocolDialect {
+        self.dialect
+    }
+
+    /// The index of the next argument
+    pub fn index(&self) -> usize {
+        self.position
+    }
+
+    /// How many arguments are left
+    pub fn remaining(&self) -> usize {
+        self.arguments.len() - self.position
+    }
+
+    /// Decodes the next argument as a `T` with [`FromKatcpArgument::from_dialect_argument_bytes`], recording its index
+    /// in any error
+    pub fn decode<T>(&mut self) -> Result<T, KatcpError>
+    where
+        T: FromKatcpArgument,
+        KatcpError: From<T::Err>,
+    {
+        let index = self.position;
+        let argument = self
+            .next()
+            .ok_or_else(|| KatcpError::missing_argument().at_index(index))?;
+        T::from_dialect_argument_bytes(argument, self.dialect).map_err(|e| e.at_index(index))
+    }
+
+    /// Decodes a group of arguments as a `T`. Errors that don't already say which argument they came from are
+    /// recorded at the one we were on.
+    pub fn decode_group<T>(&mut self) -> Result<T, KatcpError>
+    where
+        T: FromKatcpArguments,
+        KatcpError: From<T::Err>,
+    {
+        T::from_arguments(self).map_err(|e| match KatcpError::from(e) {
+            e @ KatcpError::MissingArgument { .. } => e.at_index(self.position),
+            e => e.at_index(self.position.saturating_sub(1)),
+        })
+    }
+
+    /// Checks that all of the arguments have been decoded
+    pub fn finish(&self) -> Result<(), KatcpError> {
+        if self.remaining() == 0 {
+            Ok(())
+        } else {
+            Err(
+                KatcpError::extra_arguments(self.position, self.arguments.len())
+                    .at_index(self.position),
+            )
+        }
+    }
+}
+
+impl<'a, A: AsRef<[u8]>> Iterator for ArgumentDecoder<'a, A> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let argument = self.arguments.get(self.position)?;
+        self.position += 1;
+        Some(argument.as_ref())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining(), Some(self.remaining()))
+    }
+}
+
+impl<'a, A: AsRef<[u8]>> ExactSizeIterator for ArgumentDecoder<'a, A> {}
+
+/// Decodes the next argument from `arguments`, recording `field` in the context of any error.
+/// This is useful when implementing [`FromKatcpArguments`] by hand.
+pub fn next_argument<T, A>(
+    arguments: &mut ArgumentDecoder<'_, A>,
+    field: &'static str,
+) -> Result<T, KatcpError>
+where
+    T: FromKatcpArgument,
+    KatcpError: From<T::Err>,
+    A: AsRef<[u8]>,
+{
+    arguments.decode().map_err(|e| e.in_field(field))
 }
 
 /// Marker trait for implementers of both [`ToKatcpArgument`] and [`FromKatcpArgument`]
@@ -57,8 +440,117 @@ impl<T> KatcpArgument for T where T: ToKatcpArgument + FromKatcpArgument {}
 impl<T> KatcpArguments for T where T: ToKatcpArguments + FromKatcpArguments {}
 
 /// Type alias for DateTime<Utc> from chrono
+///
+/// As an argument, it's decimal seconds since the epoch like `1427043968.954988`: an optional `-`, then digits with an
+/// optional `.` and fraction. Exponents (`1e9`), a leading `+` and the `inf` and `nan` of floats are rejected, as are
+/// timestamps outside of chrono's range.
 pub type KatcpTimestamp = DateTime<Utc>;
 
+/// Serde support for [`KatcpTimestamp`]s, which are represented as RFC 3339 strings in UTC
+/// (e.g. `"2022-07-11T16:20:00.5Z"`). Use this with `#[serde(with = "katcp::messages::common::serde_timestamp")]`
+/// or `serde_timestamp::vec` for a `Vec<KatcpTimestamp>`.
+#[cfg(feature = "serde")]
+pub mod serde_timestamp {
+    use alloc::{string::String, vec::Vec};
+
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::KatcpTimestamp;
+
+    fn to_rfc3339(timestamp: &KatcpTimestamp) -> String {
+        timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+
+    fn from_rfc3339<E: Error>(s: &str) -> Result<KatcpTimestamp, E> {
+        DateTime::parse_from_rfc3339(s)
+            .map(|timestamp| timestamp.with_timezone(&Utc))
+            .map_err(E::custom)
+    }
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &KatcpTimestamp,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_rfc3339(timestamp))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<KatcpTimestamp, D::Error> {
+        from_rfc3339(&String::deserialize(deserializer)?)
+    }
+
+    /// The same representation, for a `Vec` of timestamps
+    pub mod vec {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            timestamps: &[KatcpTimestamp],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(timestamps.iter().map(to_rfc3339))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<KatcpTimestamp>, D::Error> {
+            Vec::<String>::deserialize(deserializer)?
+                .iter()
+                .map(|s| from_rfc3339(s))
+                .collect()
+        }
+    }
+}
+
+/// Generators for the fields whose values don't all survive a trip through katcp, for use with
+/// `#[arbitrary(with = ...)]` so that every generated message round-trips exactly
+#[cfg(feature = "arbitrary")]
+pub(crate) mod arbitrary_fields {
+    use alloc::{string::String, vec::Vec};
+
+    use arbitrary::{Arbitrary, Result, Unstructured};
+    use chrono::{TimeZone, Utc};
+
+    use super::{KatcpTimestamp, RetCode};
+
+    /// Any nanosecond timestamp, but within chrono's (and so our) range
+    pub fn timestamp(u: &mut Unstructured) -> Result<KatcpTimestamp> {
+        let secs = i32::arbitrary(u)?.into();
+        Ok(Utc
+            .timestamp_opt(secs, u.int_in_range(0..=999_999_999)?)
+            .unwrap())
+    }
+
+    pub fn timestamps(u: &mut Unstructured) -> Result<Vec<KatcpTimestamp>> {
+        let len = u.arbitrary_len::<(i32, u32)>()?;
+        (0..len).map(|_| timestamp(u)).collect()
+    }
+
+    /// NaN is never equal to itself, so replace it with zero
+    pub fn float(u: &mut Unstructured) -> Result<f64> {
+        let f = f64::arbitrary(u)?;
+        Ok(if f.is_nan() { 0.0 } else { f })
+    }
+
+    pub fn floats(u: &mut Unstructured) -> Result<Vec<f64>> {
+        Ok(Vec::<f64>::arbitrary(u)?
+            .into_iter()
+            .map(|f| if f.is_nan() { 0.0 } else { f })
+            .collect())
+    }
+
+    /// `Some("")` is escaped to `\@`, which decodes as `None`
+    pub fn optional_name(u: &mut Unstructured) -> Result<Option<String>> {
+        Ok(Option::<String>::arbitrary(u)?.filter(|s| !s.is_empty()))
+    }
+
+    /// The return code of an error reply, as an `ok` code would decode as the `Ok` variant
+    pub fn error_code(u: &mut Unstructured) -> Result<RetCode> {
+        Ok(*u.choose(&[RetCode::Invalid, RetCode::Fail])?)
+    }
+}
+
 // ---- Implementations for the "core" KatcpTypes
 
 // str
@@ -78,17 +570,56 @@ impl FromKatcpArgument for String {
     type Err = KatcpError;
 
     fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
-        Ok(unescape(s.as_ref()))
+        unescape(s.as_ref())
+    }
+}
+
+// Bytes
+impl ToKatcpArgument for [u8] {
+    /// Bytes that aren't valid UTF-8 are replaced with U+FFFD, use
+    /// [`ToKatcpArgument::to_argument_bytes`] (as the derived messages do) to serialize them losslessly
+    fn to_argument(&self) -> String {
+        escape(&String::from_utf8_lossy(self))
+    }
+
+    fn to_argument_bytes(&self) -> Vec<u8> {
+        escape_bytes(self)
+    }
+}
+
+impl ToKatcpArgument for Vec<u8> {
+    /// Bytes that aren't valid UTF-8 are replaced with U+FFFD, use
+    /// [`ToKatcpArgument::to_argument_bytes`] (as the derived messages do) to serialize them losslessly
+    fn to_argument(&self) -> String {
+        self.as_slice().to_argument()
+    }
+
+    fn to_argument_bytes(&self) -> Vec<u8> {
+        escape_bytes(self)
+    }
+}
+
+impl FromKatcpArgument for Vec<u8> {
+    type Err = KatcpError;
+
+    fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
+        unescape_bytes(s.as_ref().as_bytes())
+    }
+
+    fn from_argument_bytes(b: impl AsRef<[u8]>) -> Result<Self, KatcpError> {
+        unescape_bytes(b.as_ref())
     }
 }
 
 // KatcpTimestamp
 impl ToKatcpArgument for KatcpTimestamp {
+    /// Seconds since the epoch, with as many decimal places as are needed to represent the timestamp exactly
     fn to_argument(&self) -> String {
-        let secs = self.timestamp() as f64;
-        let nano = self.timestamp_subsec_nanos();
-        let frac = nano as f64 / 1e9;
-        format!("{}", secs + frac)
+        format_timestamp(self, None)
+    }
+
+    fn to_dialect_argument_bytes(&self, dialect: ProtocolDialect) -> Vec<u8> {
+        dialect.format_timestamp(self).into_bytes()
     }
 }
 
@@ -96,10 +627,295 @@ impl FromKatcpArgument for KatcpTimestamp {
     type Err = KatcpError;
 
     fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
-        let fractional: f64 = s.as_ref().parse().map_err(|_| KatcpError::BadArgument)?;
-        let secs = fractional as i64;
-        let nanos = (fractional.fract() * 1e9) as u32;
-        Ok(Utc.timestamp(secs, nanos))
+        parse_timestamp(s.as_ref()).ok_or_else(|| KatcpError::bad_argument(s.as_ref()))
+    }
+
+    fn from_dialect_argument_bytes(
+        b: impl AsRef<[u8]>,
+        dialect: ProtocolDialect,
+    ) -> Result<Self, KatcpError> {
+        dialect.parse_timestamp(utf8_argument(b.as_ref())?)
+    }
+}
+
+/// Formats `timestamp` as decimal seconds since the epoch with exactly `places` decimal places (truncating any
+/// extra precision), or with as few as represent it exactly if `places` is `None`.
+/// This is exact, unlike going through a float.
+///
+/// ```
+/// use chrono::{TimeZone, Utc};
+/// use katcp::messages::common::format_timestamp;
+///
+/// let timestamp = Utc.timestamp_opt(1427043968, 954988000).unwrap();
+/// assert_eq!("1427043968.954988", format_timestamp(&timestamp, None));
+/// assert_eq!("1427043968.954", format_timestamp(&timestamp, Some(3)));
+/// assert_eq!("1427043968.95498800", format_timestamp(&timestamp, Some(8)));
+/// ```
+pub fn format_timestamp(timestamp: &KatcpTimestamp, places: Option<usize>) -> String {
+    let nanos = i128::from(timestamp.timestamp()) * 1_000_000_000
+        + i128::from(timestamp.timestamp_subsec_nanos());
+    format_seconds(
+        nanos < 0,
+        nanos.unsigned_abs() / 1_000_000_000,
+        (nanos.unsigned_abs() % 1_000_000_000) as u32,
+        places,
+    )
+}
+
+/// Formats `whole` seconds and `nanos` as a decimal, as described in [`format_timestamp`]
+fn format_seconds(negative: bool, whole: u128, nanos: u32, places: Option<usize>) -> String {
+    let mut fraction = format!("{:09}", nanos);
+    match places {
+        Some(places) => {
+            fraction.truncate(places);
+            while fraction.len() < places {
+                fraction.push('0');
+            }
+        }
+        None => fraction.truncate(fraction.trim_end_matches('0').len()),
+    }
+    let sign = if negative && (whole > 0 || fraction.bytes().any(|b| b != b'0')) {
+        "-"
+    } else {
+        ""
+    };
+    if fraction.is_empty() {
+        format!("{}{}", sign, whole)
+    } else {
+        format!("{}{}.{}", sign, whole, fraction)
+    }
+}
+
+/// Parses decimal seconds since the epoch exactly, in the form described at [`KatcpTimestamp`], ignoring any digits
+/// past nanoseconds
+fn parse_timestamp(s: &str) -> Option<KatcpTimestamp> {
+    let (negative, whole, nanos) = parse_seconds(s)?;
+    let nanos = i128::from(whole) * 1_000_000_000 + i128::from(nanos);
+    timestamp_from_nanos(if negative { -nanos } else { nanos })
+}
+
+/// The timestamp `nanos` nanoseconds after the epoch, if chrono can represent it
+fn timestamp_from_nanos(nanos: i128) -> Option<KatcpTimestamp> {
+    // Chrono's nanoseconds always count forwards from the second
+    let secs = i64::try_from(nanos.div_euclid(1_000_000_000)).ok()?;
+    Utc.timestamp_opt(secs, nanos.rem_euclid(1_000_000_000) as u32)
+        .single()
+}
+
+/// Splits decimal seconds into whether they're negative, the whole seconds and the nanoseconds, ignoring any digits
+/// past nanoseconds
+fn parse_seconds(s: &str) -> Option<(bool, u64, u32)> {
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, s),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return None;
+    }
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().ok()?
+    };
+    let fraction = &fraction[..fraction.len().min(9)];
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u32>().ok()? * 10u32.pow(9 - fraction.len() as u32)
+    };
+    Some((negative, whole, nanos))
+}
+
+/// A [`KatcpTimestamp`] that is always sent with exactly `PLACES` decimal places, truncating any extra precision
+/// (e.g. `FixedTimestamp<6>` for microseconds). Any number of decimal places are accepted when parsing. To a peer
+/// speaking [`ProtocolDialect::V4`], it's sent in milliseconds like any other timestamp.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct FixedTimestamp<const PLACES: usize>(pub KatcpTimestamp);
+
+impl<const PLACES: usize> ToKatcpArgument for FixedTimestamp<PLACES> {
+    fn to_argument(&self) -> String {
+        format_timestamp(&self.0, Some(PLACES))
+    }
+
+    fn to_dialect_argument_bytes(&self, dialect: ProtocolDialect) -> Vec<u8> {
+        match dialect {
+            ProtocolDialect::V4 => self.0.to_dialect_argument_bytes(dialect),
+            ProtocolDialect::V5 => self.to_argument_bytes(),
+        }
+    }
+}
+
+impl<const PLACES: usize> FromKatcpArgument for FixedTimestamp<PLACES> {
+    type Err = KatcpError;
+
+    fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
+        KatcpTimestamp::from_argument(s).map(Self)
+    }
+
+    fn from_dialect_argument_bytes(
+        b: impl AsRef<[u8]>,
+        dialect: ProtocolDialect,
+    ) -> Result<Self, KatcpError> {
+        KatcpTimestamp::from_dialect_argument_bytes(b, dialect).map(Self)
+    }
+}
+
+impl<const PLACES: usize> From<KatcpTimestamp> for FixedTimestamp<PLACES> {
+    fn from(timestamp: KatcpTimestamp) -> Self {
+        Self(timestamp)
+    }
+}
+
+/// The generation of the katcp spec a peer speaks.
+///
+/// The message types in this crate are laid out as in v5. KATCP v4 devices send integer milliseconds instead of
+/// decimal seconds for their timestamps, including those in the [`Log`](crate::messages::log::Log),
+/// [`SensorStatus`](crate::messages::sensors::SensorStatus) and [`SensorValue`](crate::messages::sensors::SensorValue)
+/// informs and the params of timestamp [`SensorList`](crate::messages::sensors::SensorList) informs, and for the
+/// periods of a [`SamplingStrategy`](crate::messages::sensors::SamplingStrategy). The derived messages pass the
+/// dialect down to each argument through the [`ArgumentEncoder`] and [`ArgumentDecoder`]. They also
+/// announce themselves with [`Version`](crate::messages::core::Version) and
+/// [`BuildState`](crate::messages::core::BuildState) informs rather than
+/// [`VersionConnect`](crate::messages::core::VersionConnect). Use [`KatcpMessage::to_dialect_message`] and
+/// [`KatcpMessage::from_dialect_message`] to talk to either.
+///
+/// ```
+/// use chrono::{TimeZone, Utc};
+/// use katcp::{
+///     messages::{common::ProtocolDialect, log::Log},
+///     prelude::*,
+/// };
+///
+/// let message: Message = "#log warn 1427043968954 device.pump Low\\_pressure"
+///     .try_into()
+///     .unwrap();
+/// let log = Log::from_dialect_message(message, ProtocolDialect::V4).unwrap();
+/// if let Log::Inform { timestamp, .. } = &log {
+///     assert_eq!(
+///         Utc.timestamp_opt(1427043968, 954000000).unwrap(),
+///         *timestamp
+///     );
+/// }
+/// assert_eq!(
+///     "#log warn 1427043968.954 device.pump Low\\_pressure\n",
+///     log.to_dialect_message(None, ProtocolDialect::V5)
+///         .unwrap()
+///         .to_string()
+/// );
+/// ```
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ProtocolDialect {
+    /// KATCP v4, with millisecond timestamps
+    V4,
+    /// KATCP v5, which the message types in this crate follow
+    V5,
+}
+
+impl Default for ProtocolDialect {
+    fn default() -> Self {
+        Self::V5
+    }
+}
+
+impl ProtocolDialect {
+    /// The dialect of a major version of katcp, as sent in the `katcp-protocol`
+    /// [`VersionConnect`](crate::messages::core::VersionConnect) inform. Later versions are assumed to be compatible
+    /// with v5.
+    pub fn from_major_version(major: u32) -> Option<Self> {
+        match major {
+            0..=3 => None,
+            4 => Some(Self::V4),
+            _ => Some(Self::V5),
+        }
+    }
+
+    /// Formats `timestamp` as an argument in this dialect. In v4, that's integer milliseconds since the epoch,
+    /// truncating any extra precision.
+    pub fn format_timestamp(self, timestamp: &KatcpTimestamp) -> String {
+        match self {
+            Self::V4 => {
+                let millis = timestamp.timestamp_millis();
+                // timestamp_millis rounds towards the past, but everything else truncates
+                if millis < 0 && timestamp.timestamp_subsec_nanos() % 1_000_000 != 0 {
+                    (millis + 1).to_string()
+                } else {
+                    millis.to_string()
+                }
+            }
+            Self::V5 => format_timestamp(timestamp, None),
+        }
+    }
+
+    /// Parses a timestamp argument in this dialect. Fractional milliseconds are accepted in v4.
+    pub fn parse_timestamp(self, s: impl AsRef<str>) -> Result<KatcpTimestamp, KatcpError> {
+        let timestamp = match self {
+            Self::V4 => parse_seconds(s.as_ref()).and_then(|(negative, millis, nanos)| {
+                let nanos = i128::from(millis) * 1_000_000 + i128::from(nanos / 1000);
+                timestamp_from_nanos(if negative { -nanos } else { nanos })
+            }),
+            Self::V5 => parse_timestamp(s.as_ref()),
+        };
+        timestamp.ok_or_else(|| KatcpError::bad_argument(s.as_ref()))
+    }
+
+    /// Formats `duration` as an argument in this dialect. In v4, that's integer milliseconds, truncating any extra
+    /// precision.
+    pub fn format_duration(self, duration: &Duration) -> String {
+        match self {
+            Self::V4 => duration.as_millis().to_string(),
+            Self::V5 => duration.to_argument(),
+        }
+    }
+
+    /// Parses a duration argument in this dialect. Fractional milliseconds are accepted in v4.
+    pub fn parse_duration(self, s: impl AsRef<str>) -> Result<Duration, KatcpError> {
+        match self {
+            Self::V4 => match parse_seconds(s.as_ref()) {
+                Some((false, millis, nanos)) => Duration::from_millis(millis)
+                    .checked_add(Duration::from_nanos(u64::from(nanos / 1000))),
+                _ => None,
+            }
+            .ok_or_else(|| KatcpError::bad_argument(s.as_ref())),
+            Self::V5 => Duration::from_argument(s),
+        }
+    }
+}
+
+// Duration
+impl ToKatcpArgument for Duration {
+    /// Seconds, with as many decimal places as are needed to represent the duration exactly
+    fn to_argument(&self) -> String {
+        format_seconds(false, u128::from(self.as_secs()), self.subsec_nanos(), None)
+    }
+
+    fn to_dialect_argument_bytes(&self, dialect: ProtocolDialect) -> Vec<u8> {
+        dialect.format_duration(self).into_bytes()
+    }
+}
+
+impl FromKatcpArgument for Duration {
+    type Err = KatcpError;
+
+    fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
+        match parse_seconds(s.as_ref()) {
+            Some((false, secs, nanos)) => Ok(Duration::new(secs, nanos)),
+            _ => Err(KatcpError::bad_argument(s.as_ref())),
+        }
+    }
+
+    fn from_dialect_argument_bytes(
+        b: impl AsRef<[u8]>,
+        dialect: ProtocolDialect,
+    ) -> Result<Self, KatcpError> {
+        dialect.parse_duration(utf8_argument(b.as_ref())?)
     }
 }
 
@@ -114,6 +930,20 @@ where
             None => r"\@".to_owned(),
         }
     }
+
+    fn to_argument_bytes(&self) -> Vec<u8> {
+        match self {
+            Some(v) => v.to_argument_bytes(),
+            None => br"\@".to_vec(),
+        }
+    }
+
+    fn to_dialect_argument_bytes(&self, dialect: ProtocolDialect) -> Vec<u8> {
+        match self {
+            Some(v) => v.to_dialect_argument_bytes(dialect),
+            None => br"\@".to_vec(),
+        }
+    }
 }
 
 impl<E, T> FromKatcpArgument for Option<T>
@@ -128,10 +958,39 @@ where
             _ => Ok(Some(T::from_argument(s)?)),
         }
     }
+
+    fn from_argument_bytes(b: impl AsRef<[u8]>) -> Result<Self, KatcpError>
+    where
+        KatcpError: From<Self::Err>,
+    {
+        match b.as_ref() {
+            br"\@" => Ok(None),
+            _ => Ok(Some(T::from_argument_bytes(b)?)),
+        }
+    }
+
+    fn from_dialect_argument_bytes(
+        b: impl AsRef<[u8]>,
+        dialect: ProtocolDialect,
+    ) -> Result<Self, KatcpError>
+    where
+        KatcpError: From<Self::Err>,
+    {
+        match b.as_ref() {
+            br"\@" => Ok(None),
+            _ => Ok(Some(T::from_dialect_argument_bytes(b, dialect)?)),
+        }
+    }
 }
 
 // Return Code
 #[derive(KatcpDiscrete, Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Return codes that form the first parameter of replys
 pub enum RetCode {
     /// Request successfully processed. Further arguments are request-specific
@@ -142,33 +1001,28 @@ pub enum RetCode {
     Fail,
 }
 
-impl ToKatcpArgument for u32 {
-    fn to_argument(&self) -> String {
-        self.to_string()
-    }
-}
-
-impl FromKatcpArgument for u32 {
-    type Err = KatcpError;
+/// Integers are sent in decimal
+macro_rules! integer_arguments {
+    ($($ty:ty),*) => {$(
+        impl ToKatcpArgument for $ty {
+            fn to_argument(&self) -> String {
+                self.to_string()
+            }
+        }
 
-    fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
-        s.as_ref().parse().map_err(|_| KatcpError::BadArgument)
-    }
-}
+        impl FromKatcpArgument for $ty {
+            type Err = KatcpError;
 
-impl ToKatcpArgument for i32 {
-    fn to_argument(&self) -> String {
-        self.to_string()
-    }
+            fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
+                s.as_ref()
+                    .parse()
+                    .map_err(|_| KatcpError::bad_argument(s.as_ref()))
+            }
+        }
+    )*};
 }
 
-impl FromKatcpArgument for i32 {
-    type Err = KatcpError;
-
-    fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
-        s.as_ref().parse().map_err(|_| KatcpError::BadArgument)
-    }
-}
+integer_arguments!(u8, u16, u32, u64, i16, i32, i64);
 
 impl ToKatcpArgument for bool {
     fn to_argument(&self) -> String {
@@ -183,25 +1037,40 @@ impl FromKatcpArgument for bool {
         match s.as_ref() {
             "1" => Ok(true),
             "0" => Ok(false),
-            _ => Err(KatcpError::BadArgument),
+            _ => Err(KatcpError::bad_argument(s.as_ref())),
         }
     }
 }
 
-impl ToKatcpArgument for f32 {
-    fn to_argument(&self) -> String {
-        format!("{}", self)
-    }
-}
+/// Floats are sent as the shortest decimal that parses back to exactly the same value. Very large and very small
+/// magnitudes use scientific notation (as `%g` would), so we never send hundreds of digits.
+macro_rules! float_arguments {
+    ($($ty:ty),*) => {$(
+        impl ToKatcpArgument for $ty {
+            fn to_argument(&self) -> String {
+                let magnitude = if *self < 0.0 { -*self } else { *self };
+                if self.is_finite() && magnitude != 0.0 && !(1e-4..1e16).contains(&magnitude) {
+                    format!("{:e}", self)
+                } else {
+                    format!("{}", self)
+                }
+            }
+        }
 
-impl FromKatcpArgument for f32 {
-    type Err = KatcpError;
+        impl FromKatcpArgument for $ty {
+            type Err = KatcpError;
 
-    fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
-        s.as_ref().parse().map_err(|_| KatcpError::BadArgument)
-    }
+            fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
+                s.as_ref()
+                    .parse()
+                    .map_err(|_| KatcpError::bad_argument(s.as_ref()))
+            }
+        }
+    )*};
 }
 
+float_arguments!(f32, f64);
+
 /// Katcp addresses optionally have a port, so we need a sum type for the two native rust
 /// types [`IpAddr`] and [`SocketAddr`], depending on whether we have a port
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -225,12 +1094,45 @@ impl ToKatcpArgument for KatcpAddress {
     }
 }
 
+/// Addresses are represented in serde by their katcp argument (e.g. `"192.168.1.2:7147"` or `"[::1]"`)
+#[cfg(feature = "serde")]
+impl serde::Serialize for KatcpAddress {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_argument())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for KatcpAddress {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::from_argument(s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Generated by hand, as the flow info and scope id of v6 socket addresses can't be represented in katcp
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for KatcpAddress {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let ip = if u.arbitrary()? {
+            IpAddr::V4(u32::arbitrary(u)?.into())
+        } else {
+            IpAddr::V6(u128::arbitrary(u)?.into())
+        };
+        Ok(if u.arbitrary()? {
+            Self::Socket(SocketAddr::new(ip, u.arbitrary()?))
+        } else {
+            Self::Ip(ip)
+        })
+    }
+}
+
 impl FromKatcpArgument for KatcpAddress {
     type Err = KatcpError;
 
     fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
         if s.as_ref().is_empty() {
-            Err(KatcpError::BadArgument)
+            Err(KatcpError::bad_argument(s.as_ref()))
         } else if let Ok(addr) = s.as_ref().parse() {
             Ok(Self::Socket(addr))
         } else if let Ok(addr) = s.as_ref().parse() {
@@ -240,35 +1142,166 @@ impl FromKatcpArgument for KatcpAddress {
             if let Ok(addr) = slice.parse() {
                 Ok(Self::Ip(addr))
             } else {
-                Err(KatcpError::BadArgument)
+                Err(KatcpError::bad_argument(s.as_ref()))
             }
         } else {
-            Err(KatcpError::BadArgument)
+            Err(KatcpError::bad_argument(s.as_ref()))
         }
     }
 }
 
+// Variadic arguments
+
+/// Every element is an argument
+impl<T: ToKatcpArgument> ToKatcpArguments for Vec<T> {
+    fn to_arguments(&self) -> Vec<String> {
+        self.iter().map(ToKatcpArgument::to_argument).collect()
+    }
+
+    fn encode_arguments<E>(&self, encoder: &mut ArgumentEncoder<'_, E>) -> Result<(), E> {
+        self.iter().try_for_each(|element| encoder.encode(element))
+    }
+}
+
+/// Consumes all of the remaining arguments
+impl<T: FromKatcpArgument> FromKatcpArguments for Vec<T>
+where
+    KatcpError: From<T::Err>,
+{
+    type Err = KatcpError;
+
+    fn from_arguments<A: AsRef<[u8]>>(
+        arguments: &mut ArgumentDecoder<'_, A>,
+    ) -> Result<Self, Self::Err> {
+        (0..arguments.remaining())
+            .map(|_| arguments.decode())
+            .collect()
+    }
+}
+
+impl<T: ToKatcpArgument, const N: usize> ToKatcpArguments for [T; N] {
+    fn to_arguments(&self) -> Vec<String> {
+        self.iter().map(ToKatcpArgument::to_argument).collect()
+    }
+
+    fn encode_arguments<E>(&self, encoder: &mut ArgumentEncoder<'_, E>) -> Result<(), E> {
+        self.iter().try_for_each(|element| encoder.encode(element))
+    }
+}
+
+/// Consumes exactly `N` arguments
+impl<T: FromKatcpArgument, const N: usize> FromKatcpArguments for [T; N]
+where
+    KatcpError: From<T::Err>,
+{
+    type Err = KatcpError;
+
+    fn from_arguments<A: AsRef<[u8]>>(
+        arguments: &mut ArgumentDecoder<'_, A>,
+    ) -> Result<Self, Self::Err> {
+        let elements = (0..N)
+            .map(|_| arguments.decode())
+            .collect::<Result<Vec<_>, _>>()?;
+        // We decoded exactly N elements
+        Ok(elements.try_into().unwrap_or_else(|_| unreachable!()))
+    }
+}
+
+/// Implements the argument traits for tuples of arguments, with one argument per element
+macro_rules! tuple_arguments {
+    ($($ty:ident $var:ident),+) => {
+        impl<$($ty: ToKatcpArgument),+> ToKatcpArguments for ($($ty,)+) {
+            fn to_arguments(&self) -> Vec<String> {
+                let ($($var,)+) = self;
+                vec![$($var.to_argument()),+]
+            }
+
+            fn encode_arguments<Fail>(
+                &self,
+                encoder: &mut ArgumentEncoder<'_, Fail>,
+            ) -> Result<(), Fail> {
+                let ($($var,)+) = self;
+                $(encoder.encode($var)?;)+
+                Ok(())
+            }
+        }
+
+        impl<$($ty: FromKatcpArgument),+> FromKatcpArguments for ($($ty,)+)
+        where
+            $(KatcpError: From<$ty::Err>),+
+        {
+            type Err = KatcpError;
+
+            fn from_arguments<Arg: AsRef<[u8]>>(
+                arguments: &mut ArgumentDecoder<'_, Arg>,
+            ) -> Result<Self, Self::Err> {
+                Ok(($(arguments.decode::<$ty>()?,)+))
+            }
+        }
+    };
+}
+
+tuple_arguments!(A a);
+tuple_arguments!(A a, B b);
+tuple_arguments!(A a, B b, C c);
+tuple_arguments!(A a, B b, C c, D d);
+tuple_arguments!(A a, B b, C c, D d, E e);
+tuple_arguments!(A a, B b, C c, D d, E e, F f);
+tuple_arguments!(A a, B b, C c, D d, E e, F f, G g);
+tuple_arguments!(A a, B b, C c, D d, E e, F f, G g, H h);
+
 /// Convienence method for round-trip testing
 pub fn roundtrip_test<T, E>(message: T)
 where
-    E: std::fmt::Debug,
-    T: KatcpMessage + PartialEq + std::fmt::Debug + TryFrom<Message, Error = E>,
+    E: core::fmt::Debug,
+    T: KatcpMessage + PartialEq + core::fmt::Debug + TryFrom<Message, Error = E>,
 {
     let raw = message.to_message(None).unwrap();
     let s = raw.to_string();
-    // Print the middle, we're using this in tests, so we'll only see it on fails
-    println!("Katcp Payload:\n{}", s);
+    #[cfg(feature = "std")]
+    {
+        let mut written = Vec::new();
+        message.write_to(None, &mut written).unwrap();
+        assert_eq!(s.as_bytes(), written.as_slice());
+    }
     let raw_test: Message = (s.as_str()).try_into().unwrap();
     let message_test = raw_test.try_into().unwrap();
-    assert_eq!(message, message_test)
+    // Include the middle, we're using this in tests, so we'll only see it on fails
+    assert_eq!(message, message_test, "Katcp Payload:\n{}", s)
+}
+
+/// Runs [`roundtrip_test`] on many generated values of `T`
+#[cfg(all(test, feature = "arbitrary"))]
+pub(crate) fn roundtrip_property<T, E>()
+where
+    E: core::fmt::Debug,
+    T: KatcpMessage
+        + PartialEq
+        + core::fmt::Debug
+        + TryFrom<Message, Error = E>
+        + for<'a> arbitrary::Arbitrary<'a>,
+{
+    use proptest::prelude::*;
+
+    proptest!(|(bytes in proptest::collection::vec(any::<u8>(), 0..512))| {
+        if let Ok(message) = T::arbitrary(&mut arbitrary::Unstructured::new(&bytes)) {
+            roundtrip_test(message);
+        }
+    });
 }
 
 #[derive(KatcpDiscrete, Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The datatypes that KATCP supports
 pub enum ArgumentType {
-    /// Represented by i32
+    /// Represented by i64
     Integer,
-    /// Represented by f32
+    /// Represented by f64
     Float,
     Boolean,
     /// Represented by [`KatcpTimestamp`]
@@ -281,20 +1314,42 @@ pub enum ArgumentType {
     String,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-/// The sum type of a vector of one of the primitive [`ArgumentType`]s
+#[derive(KatcpArguments, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[katcp(tag = "type")]
+/// The sum type of a vector of one of the primitive [`ArgumentType`]s. As arguments, it's the type followed by the
+/// values.
 pub enum ArgumentVec {
-    Integer(Vec<i32>),
-    Float(Vec<f32>),
-    Boolean(Vec<bool>),
-    Timestamp(Vec<KatcpTimestamp>),
-    String(Vec<String>),
-    Discrete(Vec<String>),
-    Address(Vec<KatcpAddress>),
+    Integer(#[katcp(flatten)] Vec<i64>),
+    Float(
+        #[katcp(flatten)]
+        #[cfg_attr(
+            feature = "arbitrary",
+            arbitrary(with = arbitrary_fields::floats)
+        )]
+        Vec<f64>,
+    ),
+    Boolean(#[katcp(flatten)] Vec<bool>),
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::messages::common::serde_timestamp::vec")
+    )]
+    Timestamp(
+        #[katcp(flatten)]
+        #[cfg_attr(
+            feature = "arbitrary",
+            arbitrary(with = arbitrary_fields::timestamps)
+        )]
+        Vec<KatcpTimestamp>,
+    ),
+    String(#[katcp(flatten)] Vec<String>),
+    Discrete(#[katcp(flatten)] Vec<String>),
+    Address(#[katcp(flatten)] Vec<KatcpAddress>),
 }
 
 impl Display for ArgumentVec {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let s = match self {
             ArgumentVec::Integer(_) => "integer",
             ArgumentVec::Float(_) => "float",
@@ -310,66 +1365,12 @@ impl Display for ArgumentVec {
     }
 }
 
-impl ToKatcpArguments for ArgumentVec {
-    fn to_arguments(&self) -> Vec<String> {
-        match self {
-            Self::Integer(v) => v.iter().map(|e| e.to_argument()).collect(),
-            Self::Float(v) => v.iter().map(|e| e.to_argument()).collect(),
-            Self::Boolean(v) => v.iter().map(|e| e.to_argument()).collect(),
-            Self::Timestamp(v) => v.iter().map(|e| e.to_argument()).collect(),
-            Self::String(v) => v.iter().map(|e| e.to_argument()).collect(),
-            Self::Discrete(v) => v.iter().map(|e| e.to_argument()).collect(),
-            Self::Address(v) => v.iter().map(|e| e.to_argument()).collect(),
-        }
-    }
-}
-
-pub(crate) fn from_argument_vec(
-    ty: &ArgumentType,
-    strings: &mut impl Iterator<Item = String>,
-) -> Result<ArgumentVec, KatcpError> {
-    Ok(match ty {
-        ArgumentType::Boolean => ArgumentVec::Boolean(
-            strings
-                .map(bool::from_argument)
-                .collect::<Result<Vec<_>, _>>()?,
-        ),
-        ArgumentType::Integer => ArgumentVec::Integer(
-            strings
-                .map(i32::from_argument)
-                .collect::<Result<Vec<_>, _>>()?,
-        ),
-        ArgumentType::Float => ArgumentVec::Float(
-            strings
-                .map(f32::from_argument)
-                .collect::<Result<Vec<_>, _>>()?,
-        ),
-        ArgumentType::Timestamp => ArgumentVec::Timestamp(
-            strings
-                .map(KatcpTimestamp::from_argument)
-                .collect::<Result<Vec<_>, _>>()?,
-        ),
-        ArgumentType::Discrete => ArgumentVec::Discrete(
-            strings
-                .map(String::from_argument)
-                .collect::<Result<Vec<_>, _>>()?,
-        ),
-        ArgumentType::Address => ArgumentVec::Address(
-            strings
-                .map(KatcpAddress::from_argument)
-                .collect::<Result<Vec<_>, _>>()?,
-        ),
-        ArgumentType::String => ArgumentVec::String(
-            strings
-                .map(String::from_argument)
-                .collect::<Result<Vec<_>, _>>()?,
-        ),
-    })
-}
-
 #[cfg(test)]
 mod test_arguments {
+    use proptest::prelude::*;
+
     use super::*;
+    use crate::protocol::MessageRef;
 
     #[test]
     fn test_string() {
@@ -377,16 +1378,209 @@ mod test_arguments {
         assert_eq!(s, String::from_argument(s.to_argument()).unwrap());
     }
 
+    #[test]
+    fn test_bytes() {
+        let b = b"\xde\xad \xbe\xef\0".to_vec();
+        assert_eq!(
+            b,
+            Vec::<u8>::from_argument_bytes(b.to_argument_bytes()).unwrap()
+        );
+        let s = b"Plain old text\n".to_vec();
+        assert_eq!(s, Vec::<u8>::from_argument(s.to_argument()).unwrap());
+        let o = Some(b"\xff".to_vec());
+        assert_eq!(
+            o,
+            Option::<Vec<u8>>::from_argument_bytes(o.to_argument_bytes()).unwrap()
+        );
+        // Types that need UTF-8 reject anything else
+        assert!(String::from_argument_bytes(b"\xff").is_err());
+        assert_eq!(
+            "caf\u{e9}",
+            String::from_argument_bytes("caf\u{e9}".as_bytes()).unwrap()
+        );
+    }
+
     #[test]
     fn test_timestamp() {
-        let ts = Utc.timestamp(42069, 42069000);
+        let ts = Utc.timestamp_opt(42069, 42069000).unwrap();
         assert_eq!(ts, KatcpTimestamp::from_argument(ts.to_argument()).unwrap());
+        // Microseconds (and nanoseconds) survive exactly
+        let ts = Utc.timestamp_opt(1427043968, 954988000).unwrap();
+        assert_eq!("1427043968.954988", ts.to_argument());
+        assert_eq!(
+            ts,
+            KatcpTimestamp::from_argument("1427043968.954988").unwrap()
+        );
+        let ts = Utc.timestamp_opt(1427043968, 1).unwrap();
+        assert_eq!("1427043968.000000001", ts.to_argument());
+        assert_eq!("42069", Utc.timestamp_opt(42069, 0).unwrap().to_argument());
+        // Before the epoch, nanoseconds still count forwards from the (earlier) second
+        let ts = Utc.timestamp_opt(-2, 500_000_000).unwrap();
+        assert_eq!("-1.5", ts.to_argument());
+        assert_eq!(ts, KatcpTimestamp::from_argument("-1.5").unwrap());
+        assert_eq!(
+            Utc.timestamp_opt(-1, 750_000_000).unwrap(),
+            KatcpTimestamp::from_argument("-.25").unwrap()
+        );
+        assert_eq!(
+            Utc.timestamp_opt(1, 123456789).unwrap(),
+            KatcpTimestamp::from_argument("1.1234567899").unwrap()
+        );
+        assert_eq!(
+            Utc.timestamp_opt(12, 0).unwrap(),
+            KatcpTimestamp::from_argument("12.").unwrap()
+        );
+        for bad in [
+            "",
+            ".",
+            "-",
+            "1e9",
+            "+1",
+            "nan",
+            "inf",
+            "1.-5",
+            "1.5.5",
+            "one",
+            "99999999999999999999",
+        ] {
+            assert!(KatcpTimestamp::from_argument(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_fixed_timestamp() {
+        let ts = Utc.timestamp_opt(1427043968, 954988000).unwrap();
+        assert_eq!("1427043968.954", FixedTimestamp::<3>(ts).to_argument());
+        assert_eq!("1427043968", FixedTimestamp::<0>(ts).to_argument());
+        assert_eq!(
+            "1427043968.954988000000",
+            FixedTimestamp::<12>(ts).to_argument()
+        );
+        assert_eq!(
+            "-0.500",
+            FixedTimestamp::<3>(Utc.timestamp_opt(-1, 500_000_000).unwrap()).to_argument()
+        );
+        assert_eq!(
+            "0",
+            FixedTimestamp::<0>(Utc.timestamp_opt(-1, 500_000_000).unwrap()).to_argument()
+        );
+        assert_eq!(
+            FixedTimestamp::<6>(ts),
+            FixedTimestamp::from_argument("1427043968.954988").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_dialect_timestamp() {
+        use ProtocolDialect::*;
+
+        let ts = Utc.timestamp_opt(1427043968, 954988000).unwrap();
+        assert_eq!("1427043968954", V4.format_timestamp(&ts));
+        assert_eq!("1427043968.954988", V5.format_timestamp(&ts));
+        assert_eq!(
+            Utc.timestamp_opt(1427043968, 954000000).unwrap(),
+            V4.parse_timestamp("1427043968954").unwrap()
+        );
+        assert_eq!(ts, V4.parse_timestamp("1427043968954.988").unwrap());
+        assert_eq!(ts, V5.parse_timestamp("1427043968.954988").unwrap());
+        // Before the epoch, the milliseconds are truncated towards it like the decimals are
+        let ts = Utc.timestamp_opt(-2, 499_500_000).unwrap();
+        assert_eq!("-1500", V4.format_timestamp(&ts));
+        assert_eq!(
+            Utc.timestamp_opt(-2, 500_000_000).unwrap(),
+            V4.parse_timestamp("-1500").unwrap()
+        );
+        assert!(V4.parse_timestamp("soon").is_err());
+        // So are durations
+        assert_eq!(
+            "2500",
+            V4.format_duration(&Duration::from_micros(2_500_900))
+        );
+        assert_eq!(
+            "2.5009",
+            V5.format_duration(&Duration::from_micros(2_500_900))
+        );
+        assert_eq!(
+            Duration::from_micros(2_500_900),
+            V4.parse_duration("2500.9").unwrap()
+        );
+        assert!(V4.parse_duration("-1").is_err());
+        assert_eq!(
+            Some(Utc.timestamp_opt(1, 500_000_000).unwrap()),
+            Option::<KatcpTimestamp>::from_dialect_argument_bytes("1500", V4).unwrap()
+        );
+        assert_eq!(
+            b"1500".to_vec(),
+            FixedTimestamp::<3>(Utc.timestamp_opt(1, 500_000_000).unwrap())
+                .to_dialect_argument_bytes(V4)
+        );
+        assert_eq!(V5, ProtocolDialect::default());
+        assert_eq!(None, ProtocolDialect::from_major_version(3));
+        assert_eq!(Some(V4), ProtocolDialect::from_major_version(4));
+        assert_eq!(Some(V5), ProtocolDialect::from_major_version(5));
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip_v4_timestamp(millis in -8_000_000_000_000_000i64..8_000_000_000_000_000) {
+            let ts = Utc.timestamp_millis_opt(millis).unwrap();
+            let v4 = ProtocolDialect::V4;
+            prop_assert_eq!(ts, v4.parse_timestamp(v4.format_timestamp(&ts)).unwrap());
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip_timestamp(
+            secs in -8_000_000_000_000i64..8_000_000_000_000,
+            nanos in 0u32..1_000_000_000,
+        ) {
+            let ts = Utc.timestamp_opt(secs, nanos).unwrap();
+            prop_assert_eq!(ts, KatcpTimestamp::from_argument(ts.to_argument()).unwrap());
+        }
+
+        #[test]
+        fn roundtrip_duration(secs: u64, nanos in 0u32..1_000_000_000) {
+            let duration = Duration::new(secs, nanos);
+            prop_assert_eq!(duration, Duration::from_argument(duration.to_argument()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!("0", Duration::ZERO.to_argument());
+        assert_eq!("2.5", Duration::from_millis(2500).to_argument());
+        assert_eq!("0.000000001", Duration::from_nanos(1).to_argument());
+        assert_eq!(
+            Duration::from_millis(30_250),
+            Duration::from_argument("30.25").unwrap()
+        );
+        assert_eq!(
+            Duration::from_secs(7),
+            Duration::from_argument("7.").unwrap()
+        );
+        for bad in [
+            "-1",
+            "-0.5",
+            "1e3",
+            "+2",
+            "",
+            ".",
+            "inf",
+            "18446744073709551616",
+        ] {
+            assert!(Duration::from_argument(bad).is_err(), "{}", bad);
+        }
     }
 
     #[test]
     fn test_option() {
         let s = Some("\tFoo a bar\n".to_owned());
-        assert_eq!(s, Option::<String>::from_argument(s.to_argument()).unwrap())
+        assert_eq!(s, Option::<String>::from_argument(s.to_argument()).unwrap());
+        // Both are sent as `\@`, so an empty string comes back as `None`
+        assert_eq!(r"\@", Some(String::new()).to_argument());
+        assert_eq!(r"\@", None::<String>.to_argument());
+        assert_eq!(None, Option::<String>::from_argument(r"\@").unwrap());
     }
 
     #[test]
@@ -401,6 +1595,21 @@ mod test_arguments {
         let neg_int = -12345;
         assert_eq!(pos_int, u32::from_argument(pos_int.to_argument()).unwrap());
         assert_eq!(neg_int, i32::from_argument(neg_int.to_argument()).unwrap());
+        // Packet counters and the like outgrow 32 bits
+        assert_eq!("18446744073709551615", u64::MAX.to_argument());
+        assert_eq!(
+            u64::MAX,
+            u64::from_argument("18446744073709551615").unwrap()
+        );
+        assert_eq!(
+            i64::MIN,
+            i64::from_argument(i64::MIN.to_argument()).unwrap()
+        );
+        assert_eq!(255, u8::from_argument("255").unwrap());
+        assert_eq!(-300, i16::from_argument("-300").unwrap());
+        assert_eq!(65535, u16::from_argument("65535").unwrap());
+        assert!(u8::from_argument("256").is_err());
+        assert!(u16::from_argument("-1").is_err());
     }
 
     #[test]
@@ -419,6 +1628,106 @@ mod test_arguments {
         assert_eq!(a, f32::from_argument(a.to_argument()).unwrap());
         assert_eq!(b, f32::from_argument(b.to_argument()).unwrap());
         assert_eq!(c, f32::from_argument(c.to_argument()).unwrap());
+        // NaN isn't equal to itself, but it does come back
+        assert_eq!("NaN", f64::NAN.to_argument());
+        assert!(f64::from_argument(f64::NAN.to_argument()).unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_float_formatting() {
+        assert_eq!("100", 100f32.to_argument());
+        assert_eq!("1420405751.768", 1420405751.768f64.to_argument());
+        assert_eq!("1e300", 1e300f64.to_argument());
+        assert_eq!("-2.5e-7", (-2.5e-7f64).to_argument());
+        assert_eq!("0.0001", 1e-4f32.to_argument());
+        assert_eq!("inf", f64::INFINITY.to_argument());
+        assert_eq!(1.5e10, f64::from_argument("1.5e+10").unwrap());
+        assert!(f64::from_argument("NaN").unwrap().is_nan());
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip_f64(f in any::<f64>().prop_filter("NaN", |f| !f.is_nan())) {
+            prop_assert_eq!(f.to_bits(), f64::from_argument(f.to_argument()).unwrap().to_bits());
+        }
+
+        #[test]
+        fn roundtrip_f32(f in any::<f32>().prop_filter("NaN", |f| !f.is_nan())) {
+            prop_assert_eq!(f.to_bits(), f32::from_argument(f.to_argument()).unwrap().to_bits());
+        }
+    }
+
+    #[test]
+    fn test_variadic() {
+        let args = |args: &[&str]| args.iter().map(|s| (*s).to_owned()).collect::<Vec<_>>();
+        let v = vec![1u32, 2, 3];
+        assert_eq!(args(&["1", "2", "3"]), v.to_arguments());
+        assert_eq!(
+            v,
+            Vec::<u32>::from_arguments(&mut ArgumentDecoder::new(&v.to_arguments())).unwrap()
+        );
+        assert!(
+            Vec::<u32>::from_arguments(&mut ArgumentDecoder::new(&args(&[])))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(Vec::<u32>::from_arguments(&mut ArgumentDecoder::new(&args(&["1", "x"]))).is_err());
+
+        let t = ("hello world".to_owned(), -4i32, true);
+        assert_eq!(args(&[r"hello\_world", "-4", "1"]), t.to_arguments());
+        assert_eq!(
+            t,
+            <(String, i32, bool)>::from_arguments(&mut ArgumentDecoder::new(&t.to_arguments()))
+                .unwrap()
+        );
+        assert!(matches!(
+            <(String, i32, bool)>::from_arguments(&mut ArgumentDecoder::new(&args(&["a", "1"]))),
+            Err(KatcpError::MissingArgument { .. })
+        ));
+
+        let a = [0.5f64, 1.5];
+        assert_eq!(args(&["0.5", "1.5"]), a.to_arguments());
+        let raw = args(&["0.5", "1.5", "extra"]);
+        let mut arguments = ArgumentDecoder::new(&raw);
+        assert_eq!(a, <[f64; 2]>::from_arguments(&mut arguments).unwrap());
+        // Only the array's arguments are consumed
+        assert_eq!(Some(&b"extra"[..]), arguments.next());
+        assert!(matches!(
+            <[f64; 2]>::from_arguments(&mut ArgumentDecoder::new(&args(&["0.5"]))),
+            Err(KatcpError::MissingArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decoder() {
+        let message = MessageRef::try_from("?set-gains 3 0.5 x").unwrap();
+        let mut arguments = ArgumentDecoder::new(message.arguments());
+        assert_eq!(3, arguments.decode::<u32>().unwrap());
+        assert_eq!((1, 2), (arguments.index(), arguments.remaining()));
+        assert_eq!(
+            Err(KatcpError::ExtraArguments {
+                expected: 1,
+                found: 3,
+                context: crate::protocol::ArgumentContext {
+                    index: Some(1),
+                    ..Default::default()
+                },
+            }),
+            arguments.finish()
+        );
+        // Errors in a group are recorded at the argument they happened on
+        assert_eq!(
+            Some(2),
+            arguments
+                .decode_group::<Vec<f64>>()
+                .unwrap_err()
+                .context()
+                .and_then(|context| context.index)
+        );
+        assert!(matches!(
+            ArgumentDecoder::new(&[""; 0]).decode::<u32>(),
+            Err(KatcpError::MissingArgument { .. })
+        ));
     }
 
     #[test]
@@ -449,3 +1758,386 @@ mod test_arguments {
         );
     }
 }
+
+#[cfg(test)]
+mod test_variadic_messages {
+    use alloc::{borrow::ToOwned, string::ToString, vec, vec::Vec};
+
+    use katcp_derive::KatcpMessage;
+
+    use crate::{messages::common::roundtrip_test, prelude::*, protocol::ArgumentContext};
+
+    #[derive(KatcpMessage, Debug, PartialEq, Clone)]
+    enum SetGains {
+        Request {
+            channel: u32,
+            #[katcp(flatten)]
+            gains: Vec<f64>,
+        },
+        Reply {
+            ret_code: RetCode,
+            #[katcp(flatten)]
+            range: (f64, f64),
+        },
+        Inform([i16; 3]),
+    }
+
+    #[test]
+    fn test_variadic_fields() {
+        roundtrip_test(SetGains::Request {
+            channel: 3,
+            gains: vec![0.5, 1.25, -2.0],
+        });
+        roundtrip_test(SetGains::Request {
+            channel: 3,
+            gains: vec![],
+        });
+        roundtrip_test(SetGains::Reply {
+            ret_code: RetCode::Ok,
+            range: (-1.0, 1e-6),
+        });
+        roundtrip_test(SetGains::Inform([1, -2, 3]));
+        assert_eq!(
+            "?set-gains 3 0.5 1.25 -2",
+            SetGains::Request {
+                channel: 3,
+                gains: vec![0.5, 1.25, -2.0],
+            }
+            .to_message(None)
+            .unwrap()
+            .to_string()
+            .trim_end()
+        );
+    }
+
+    #[test]
+    fn test_variadic_errors() {
+        assert_eq!(
+            Err(KatcpError::BadArgument {
+                raw: "x".to_owned(),
+                context: crate::protocol::ArgumentContext {
+                    message: Some("set-gains"),
+                    field: Some("gains"),
+                    index: Some(2),
+                },
+            }),
+            SetGains::try_from("?set-gains 3 0.5 x 1")
+        );
+        assert_eq!(
+            Err(KatcpError::MissingArgument {
+                context: crate::protocol::ArgumentContext {
+                    message: Some("set-gains"),
+                    field: Some("range"),
+                    index: Some(2),
+                },
+            }),
+            SetGains::try_from("!set-gains ok 0.5")
+        );
+        assert_eq!(
+            Some(&ArgumentContext {
+                message: Some("set-gains"),
+                field: None,
+                index: Some(2),
+            }),
+            SetGains::try_from(MessageRef::try_from("#set-gains 1 2").unwrap())
+                .unwrap_err()
+                .context()
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_attributes {
+    use alloc::{
+        borrow::ToOwned,
+        string::{String, ToString},
+    };
+
+    use katcp_derive::{KatcpDiscrete, KatcpMessage};
+
+    use crate::{
+        messages::{common::roundtrip_test, core::Help},
+        prelude::*,
+    };
+
+    #[derive(KatcpDiscrete, Debug, PartialEq, Eq, Clone, Copy)]
+    enum Receiver {
+        #[katcp(rename = "OFF")]
+        Off,
+        LBand,
+        #[katcp(rename = "3ghz")]
+        ThreeGhz,
+    }
+
+    #[derive(KatcpMessage, Debug, PartialEq, Clone)]
+    #[katcp(
+        name = "capture-start",
+        usage = "?capture-start band [duration [label]]"
+    )]
+    /// Starts capturing from the digitiser
+    enum DigitiserCapture {
+        Request {
+            band: Receiver,
+            #[katcp(skip_if_none)]
+            duration: Option<f64>,
+            #[katcp(skip_if_none)]
+            label: Option<String>,
+        },
+        Reply(IntReply),
+    }
+
+    #[derive(KatcpMessage, Debug, PartialEq, Clone)]
+    enum SetGain {
+        /// Sets the `gain` of the current [`Receiver`] in dB, see [the manual](https://example.com/manual) or
+        /// [`IntReply`][crate::messages::core::IntReply] for the reply and [Help] for more. ?set-gain gain [port]
+        Request { gain: f64 },
+        #[katcp(allow_extra)]
+        Reply(IntReply),
+    }
+
+    #[test]
+    fn test_rename() {
+        assert_eq!("OFF", Receiver::Off.to_argument());
+        assert_eq!("l-band", Receiver::LBand.to_argument());
+        assert_eq!("3ghz", Receiver::ThreeGhz.to_argument());
+        assert_eq!(Receiver::ThreeGhz, Receiver::from_argument("3ghz").unwrap());
+        assert!(Receiver::from_argument("off").is_err());
+        assert!(Receiver::from_argument("three-ghz").is_err());
+    }
+
+    #[test]
+    fn test_name() {
+        let message = DigitiserCapture::Reply(IntReply::Ok { num: 1 })
+            .to_message(None)
+            .unwrap();
+        assert_eq!("capture-start", message.name());
+        assert_eq!("capture-start", DigitiserCapture::NAME);
+        assert!(matches!(
+            DigitiserCapture::try_from("!digitiser-capture ok 1"),
+            Err(KatcpError::IncorrectType {
+                expected: "capture-start",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_usage() {
+        assert_eq!(DigitiserCapture::help(), Help::Inform {
+            name: "capture-start".to_owned(),
+            description: "?capture-start band [duration [label]]".to_owned(),
+        });
+    }
+
+    #[test]
+    fn test_help_markup() {
+        assert_eq!(SetGain::help(), Help::Inform {
+            name: "set-gain".to_owned(),
+            description: "Sets the gain of the current Receiver in dB, see the manual or IntReply for the reply and \
+                          Help for more. ?set-gain gain [port]"
+                .to_owned(),
+        });
+    }
+
+    #[test]
+    fn test_skip_if_none() {
+        let request = |duration, label: Option<&str>| DigitiserCapture::Request {
+            band: Receiver::LBand,
+            duration,
+            label: label.map(str::to_owned),
+        };
+        let raw = |message: DigitiserCapture| message.to_message(None).unwrap().to_string();
+        assert_eq!("?capture-start l-band\n", raw(request(None, None)));
+        assert_eq!("?capture-start l-band 1.5\n", raw(request(Some(1.5), None)));
+        // Only trailing `None`s can be left out
+        assert_eq!(
+            "?capture-start l-band \\@ noise\n",
+            raw(request(None, Some("noise")))
+        );
+        roundtrip_test(request(None, None));
+        roundtrip_test(request(Some(1.5), None));
+        roundtrip_test(request(None, Some("noise")));
+        roundtrip_test(request(Some(-1e-3), Some("noise source")));
+    }
+
+    #[test]
+    fn test_extra_arguments() {
+        let error = SetGain::try_from("?set-gain 1.5 2.5 3.5").unwrap_err();
+        assert_eq!(error, KatcpError::ExtraArguments {
+            expected: 1,
+            found: 3,
+            context: crate::protocol::ArgumentContext {
+                message: Some("set-gain"),
+                field: None,
+                index: Some(1),
+            },
+        });
+        assert_eq!(
+            "too many arguments, expected at most 1 but found 3 at argument 1 of `set-gain`",
+            error.to_string()
+        );
+        // Unit variants and the types inside unnamed variants are checked too
+        assert!(matches!(
+            crate::messages::core::Halt::try_from("?halt now"),
+            Err(KatcpError::ExtraArguments {
+                expected: 0,
+                found: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            crate::messages::core::Watchdog::try_from("!watchdog ok 1"),
+            Err(KatcpError::ExtraArguments {
+                expected: 1,
+                found: 2,
+                ..
+            })
+        ));
+        // Trailing optional arguments still count
+        assert!(DigitiserCapture::try_from("?capture-start l-band 1.5 noise").is_ok());
+        assert!(matches!(
+            DigitiserCapture::try_from("?capture-start l-band 1.5 noise source"),
+            Err(KatcpError::ExtraArguments {
+                expected: 3,
+                found: 4,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_allow_extra() {
+        assert_eq!(
+            SetGain::Reply(IntReply::Ok { num: 3 }),
+            SetGain::try_from("!set-gain ok 3 dB").unwrap()
+        );
+        roundtrip_test(SetGain::Request { gain: 0.5 });
+        roundtrip_test(SetGain::Reply(IntReply::Ok { num: 3 }));
+    }
+}
+
+#[cfg(test)]
+mod test_argument_groups {
+    use alloc::{borrow::ToOwned, string::String};
+
+    use katcp_derive::{KatcpArguments, KatcpMessage};
+
+    use crate::{messages::common::roundtrip_test, prelude::*, protocol::ArgumentContext};
+
+    #[derive(KatcpArguments, Debug, PartialEq, Clone)]
+    struct Window(f64, f64);
+
+    #[derive(KatcpArguments, Debug, PartialEq, Clone)]
+    #[katcp(tag = "mode")]
+    enum Trigger {
+        Manual,
+        #[katcp(rename = "ext")]
+        External {
+            channel: u8,
+        },
+        Level {
+            threshold: f64,
+            #[katcp(flatten)]
+            window: Window,
+        },
+    }
+
+    #[derive(KatcpArguments, Debug, PartialEq, Clone)]
+    struct Acquisition {
+        label: String,
+        #[katcp(skip_if_none)]
+        samples: Option<u32>,
+        #[katcp(flatten, skip_if_none)]
+        trigger: Option<Trigger>,
+    }
+
+    #[derive(KatcpMessage, Debug, PartialEq, Clone)]
+    enum Acquire {
+        Request(Acquisition),
+        Reply(IntReply),
+    }
+
+    fn args(group: &impl ToKatcpArguments) -> String {
+        group.to_arguments().join(" ")
+    }
+
+    #[test]
+    fn test_struct() {
+        assert_eq!("0.5 2.5", args(&Window(0.5, 2.5)));
+        let acquisition = |samples, trigger| Acquisition {
+            label: "noise diode".to_owned(),
+            samples,
+            trigger,
+        };
+        assert_eq!(r"noise\_diode", args(&acquisition(None, None)));
+        assert_eq!(r"noise\_diode 10", args(&acquisition(Some(10), None)));
+        assert_eq!(
+            r"noise\_diode \@ manual",
+            args(&acquisition(None, Some(Trigger::Manual)))
+        );
+        roundtrip_test(Acquire::Request(acquisition(None, None)));
+        roundtrip_test(Acquire::Request(acquisition(Some(10), None)));
+        roundtrip_test(Acquire::Request(acquisition(None, Some(Trigger::Manual))));
+        roundtrip_test(Acquire::Request(acquisition(
+            Some(10),
+            Some(Trigger::Level {
+                threshold: -3.5,
+                window: Window(0.0, 1e-3),
+            }),
+        )));
+    }
+
+    #[test]
+    fn test_enum() {
+        assert_eq!("manual", args(&Trigger::Manual));
+        assert_eq!("ext 2", args(&Trigger::External { channel: 2 }));
+        assert_eq!(
+            "level 1.5 0 1",
+            args(&Trigger::Level {
+                threshold: 1.5,
+                window: Window(0.0, 1.0),
+            })
+        );
+        assert_eq!(
+            Acquire::try_from("?acquire x 1 ext 4").unwrap(),
+            Acquire::Request(Acquisition {
+                label: "x".to_owned(),
+                samples: Some(1),
+                trigger: Some(Trigger::External { channel: 4 }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Acquire::try_from("?acquire x 1 external 4").unwrap_err(),
+            KatcpError::BadArgument {
+                raw: "external".to_owned(),
+                context: ArgumentContext {
+                    message: Some("acquire"),
+                    field: Some("mode"),
+                    index: Some(2),
+                },
+            }
+        );
+        assert_eq!(
+            Acquire::try_from("?acquire x 1 level 1.5 0").unwrap_err(),
+            KatcpError::MissingArgument {
+                context: ArgumentContext {
+                    message: Some("acquire"),
+                    field: Some("1"),
+                    index: Some(5),
+                },
+            }
+        );
+        assert!(matches!(
+            Acquire::try_from("?acquire x 1 manual 2"),
+            Err(KatcpError::ExtraArguments {
+                expected: 3,
+                found: 4,
+                ..
+            })
+        ));
+    }
+}
@@ -1,25 +1,45 @@
 //! Messages dealing with connecting to a device, halting it or restarting it and querying basic information
 
-use std::{collections::HashSet, fmt::Display};
-
-use katcp_derive::{KatcpDiscrete, KatcpMessage};
-use rustc_version;
-
-use crate::prelude::*;
+use alloc::{
+    borrow::ToOwned,
+    collections::BTreeSet,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt::Display, time::Duration};
+
+use katcp_derive::{KatcpArguments, KatcpDiscrete, KatcpMessage};
+
+#[cfg(feature = "arbitrary")]
+use crate::messages::common::arbitrary_fields;
+use crate::{messages::common::next_argument, prelude::*};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// A Reply type that contains no data in the Ok branch or a message in the error branch
 pub enum GenericReply {
     Ok,
-    Error { ret_code: RetCode, message: String },
+    Error {
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::error_code))]
+        ret_code: RetCode,
+        message: String,
+    },
 }
 
 impl ToKatcpArguments for GenericReply {
     fn to_arguments(&self) -> Vec<String> {
+        ArgumentEncoder::collect(|encoder| self.encode_arguments(encoder))
+    }
+
+    fn encode_arguments<E>(&self, encoder: &mut ArgumentEncoder<'_, E>) -> Result<(), E> {
         match self {
-            Self::Ok => vec![(RetCode::Ok).to_argument()],
+            Self::Ok => encoder.encode(&RetCode::Ok),
             Self::Error { ret_code, message } => {
-                vec![ret_code.to_argument(), message.clone().to_argument()]
+                encoder.encode(ret_code)?;
+                encoder.encode(message)
             }
         }
     }
@@ -28,19 +48,23 @@ impl ToKatcpArguments for GenericReply {
 impl FromKatcpArguments for GenericReply {
     type Err = KatcpError;
 
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err> {
-        let ret_code = RetCode::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
+    fn from_arguments<A: AsRef<[u8]>>(
+        arguments: &mut ArgumentDecoder<'_, A>,
+    ) -> Result<Self, Self::Err> {
+        let ret_code = next_argument(arguments, "ret_code")?;
         Ok(match ret_code {
             RetCode::Ok => Self::Ok,
             _ => Self::Error {
                 ret_code,
-                message: String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?,
+                message: next_argument(arguments, "message")?,
             },
         })
     }
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Requesting a Halt should trigger a software halt
 /// It is expected to close the connection and put the
 /// software and hardware into a state where it is safe to power down. The reply message should be sent just
@@ -51,20 +75,34 @@ pub enum Halt {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// A Reply type that is an integer in the Ok branch or a message in the error branch
 pub enum IntReply {
-    Ok { num: u32 },
-    Error { ret_code: RetCode, message: String },
+    Ok {
+        num: u32,
+    },
+    Error {
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::error_code))]
+        ret_code: RetCode,
+        message: String,
+    },
 }
 
 impl ToKatcpArguments for IntReply {
     fn to_arguments(&self) -> Vec<String> {
+        ArgumentEncoder::collect(|encoder| self.encode_arguments(encoder))
+    }
+
+    fn encode_arguments<E>(&self, encoder: &mut ArgumentEncoder<'_, E>) -> Result<(), E> {
         match self {
             Self::Ok { num } => {
-                vec![(RetCode::Ok).to_argument(), num.to_argument()]
+                encoder.encode(&RetCode::Ok)?;
+                encoder.encode(num)
             }
             Self::Error { ret_code, message } => {
-                vec![ret_code.to_argument(), message.clone().to_argument()]
+                encoder.encode(ret_code)?;
+                encoder.encode(message)
             }
         }
     }
@@ -73,22 +111,25 @@ impl ToKatcpArguments for IntReply {
 impl FromKatcpArguments for IntReply {
     type Err = KatcpError;
 
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err> {
-        let ret_code = RetCode::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        let num_or_msg = strings.next().ok_or(KatcpError::MissingArgument)?;
+    fn from_arguments<A: AsRef<[u8]>>(
+        arguments: &mut ArgumentDecoder<'_, A>,
+    ) -> Result<Self, Self::Err> {
+        let ret_code = next_argument(arguments, "ret_code")?;
         Ok(match ret_code {
             RetCode::Ok => Self::Ok {
-                num: u32::from_argument(num_or_msg)?,
+                num: next_argument(arguments, "num")?,
             },
             _ => Self::Error {
                 ret_code,
-                message: String::from_argument(num_or_msg)?,
+                message: next_argument(arguments, "message")?,
             },
         })
     }
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The core help message type
 pub enum Help {
     /// Although the description is not intended to be machine readable, the preferred convention for describing
@@ -107,12 +148,15 @@ pub enum Help {
     /// this request. If the name parameter does not correspond to a request on the device, a reply with a failure
     /// code and message should be sent
     Request {
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::optional_name))]
         name: Option<String>,
     },
     Reply(IntReply),
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Requesting a restart should trigger a software reset. It is expected to close the connection, reload the
 /// software and begin execution again, preferably without changing the hardware configuration (if possible).
 /// It would end with the device being ready to accept new connections again. The reply should be sent before
@@ -123,6 +167,8 @@ pub enum Restart {
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Requesting a watchdog may be sent by the client occasionally to check that the connection to the
 /// device is still active. The device should respond with a success reply if it receives the watchdog request
 pub enum Watchdog {
@@ -131,6 +177,8 @@ pub enum Watchdog {
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Before sending a reply the ?version-list command will send a
 /// series of #version-list informs. The list of informs should include all of the roles and components
 /// returned via #version-connect but may contain additional roles or components.
@@ -150,9 +198,35 @@ pub enum VersionList {
     Reply(IntReply),
 }
 
+#[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+/// Hints for how long a client should wait for the reply to a request before timing out, for devices with the
+/// [`ProtocolFlags::TimeoutHints`] flag. Requests that aren't slow don't need a hint.
+pub enum RequestTimeoutHint {
+    /// Before sending a reply, the request-timeout-hint request will send a request-timeout-hint inform message
+    /// for each request with a hint. If a name parameter is specified, only the hint for that request will be
+    /// sent, which is a hint of zero if it doesn't have one. On success the first reply parameter after the status
+    /// code will contain the number of inform messages generated by this request.
+    Request {
+        #[katcp(skip_if_none)]
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::optional_name))]
+        name: Option<String>,
+    },
+    Inform {
+        /// The name of the request the hint is for
+        name: String,
+        /// How long to wait for the reply, sent as seconds. Zero means there's no hint
+        timeout: Duration,
+    },
+    Reply(IntReply),
+}
+
 // Async informs, these only have `Inform` fields
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Sent to the client by the device shortly before the client is disconnected. In the case where a client is being
 /// disconnected because a new client has connected, the message should include
 /// the IP number and port of the new client for tracking purposes.
@@ -160,21 +234,27 @@ pub enum Disconnect {
     Inform { message: String },
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Flags from [VersionConnect] that indicate the device's features
 pub enum ProtocolFlags {
     /// the server supports multiple clients. Absence of this flag indicates that only a single client is supported
     MultiClient,
     /// the server supports message identifiers
     MessageIds,
-    /// the server provides request timeout hints
+    /// the server provides request timeout hints, see [`RequestTimeoutHint`]
     TimeoutHints,
     /// the server supports setting sensor sampling in bulk
     BulkSampling,
 }
 
 impl Display for ProtocolFlags {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", match self {
             ProtocolFlags::MultiClient => "M",
             ProtocolFlags::MessageIds => "I",
@@ -193,19 +273,21 @@ impl TryFrom<String> for ProtocolFlags {
             "I" => Ok(Self::MessageIds),
             "T" => Ok(Self::TimeoutHints),
             "B" => Ok(Self::BulkSampling),
-            _ => Err(KatcpError::BadArgument),
+            _ => Err(KatcpError::bad_argument(value)),
         }
     }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The three different types of [`VersionConnect`] inform messages
 pub enum VersionConnectInform {
     /// The version of katcp and the options it supports
     KatcpProtocol {
         major: u32,
         minor: u32,
-        flags: HashSet<ProtocolFlags>,
+        flags: BTreeSet<ProtocolFlags>,
     },
     /// Specifies the specific katcp library that the device is using
     KatcpLibrary {
@@ -220,12 +302,23 @@ pub enum VersionConnectInform {
     },
     /// Fallback for the other custom messages
     Custom {
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = custom_name))]
         name: String,
         version: String,
         info: Option<String>,
     },
 }
 
+/// Custom informs can't use the names of the other variants, as they would decode as those instead
+#[cfg(feature = "arbitrary")]
+fn custom_name(u: &mut arbitrary::Unstructured) -> arbitrary::Result<String> {
+    let mut name: String = u.arbitrary()?;
+    if ["katcp-protocol", "katcp-library", "katcp-device"].contains(&name.as_str()) {
+        name.insert(0, 'x');
+    }
+    Ok(name)
+}
+
 impl ToKatcpArguments for VersionConnectInform {
     fn to_arguments(&self) -> Vec<String> {
         match self {
@@ -280,27 +373,32 @@ impl ToKatcpArguments for VersionConnectInform {
 impl FromKatcpArguments for VersionConnectInform {
     type Err = KatcpError;
 
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err> {
-        let inform_type = strings.next().ok_or(KatcpError::MissingArgument)?;
+    fn from_arguments<A: AsRef<[u8]>>(
+        arguments: &mut ArgumentDecoder<'_, A>,
+    ) -> Result<Self, Self::Err> {
+        let inform_type: String = next_argument(arguments, "name")?;
         match inform_type.as_str() {
             "katcp-protocol" => {
-                let version_str = strings.next().ok_or(KatcpError::MissingArgument)?;
+                let version_str: String = next_argument(arguments, "version")?;
+                let bad_version =
+                    || KatcpError::bad_argument(version_str.as_str()).in_field("version");
                 let (major, minor_and_flags) =
-                    version_str.split_once('.').ok_or(KatcpError::BadArgument)?;
-                let major = major.parse().map_err(|_| KatcpError::BadArgument)?;
+                    version_str.split_once('.').ok_or_else(bad_version)?;
+                let major = major.parse().map_err(|_| bad_version())?;
                 let split = minor_and_flags.split_once('-');
                 let (minor, flags) = if let Some((minor, flagset)) = split {
                     let flags = flagset
                         .chars()
                         .map(|c| c.to_string().try_into())
-                        .collect::<Result<HashSet<_>, _>>()?;
-                    (minor.parse().map_err(|_| KatcpError::BadArgument)?, flags)
+                        .collect::<Result<BTreeSet<_>, KatcpError>>()
+                        .map_err(|e| e.in_field("flags"))?;
+                    (minor.parse().map_err(|_| bad_version())?, flags)
                 } else {
                     (
                         minor_and_flags // if let didn't match, so minor_and_flags is only minor
                             .parse()
-                            .map_err(|_| KatcpError::BadArgument)?,
-                        HashSet::new(),
+                            .map_err(|_| bad_version())?,
+                        BTreeSet::new(),
                     )
                 };
                 Ok(Self::KatcpProtocol {
@@ -310,32 +408,29 @@ impl FromKatcpArguments for VersionConnectInform {
                 })
             }
             "katcp-library" => Ok(Self::KatcpLibrary {
-                version: String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?,
-                build_state: String::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
+                version: next_argument(arguments, "version")?,
+                build_state: next_argument(arguments, "build_state")?,
             }),
             "katcp-device" => Ok(Self::KatcpDevice {
-                api_version: String::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
-                device: KatcpAddress::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
-                build_state: String::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
+                api_version: next_argument(arguments, "api_version")?,
+                device: next_argument(arguments, "device")?,
+                build_state: next_argument(arguments, "build_state")?,
             }),
             _ => Ok(Self::Custom {
                 name: inform_type,
-                version: String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?,
-                info: strings.next().map(String::from_argument).transpose()?,
+                version: next_argument(arguments, "version")?,
+                info: match arguments.remaining() {
+                    0 => None,
+                    _ => Some(next_argument(arguments, "info")?),
+                },
             }),
         }
     }
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Sent to the client when it connects. These inform messages use the same argument format as [`VersionList`]
 /// and all roles and components declared via [`VersionConnect`] should be included in the informs sent in
 /// response to [`VersionList`].
@@ -345,6 +440,7 @@ pub enum VersionConnect {
 
 impl VersionConnect {
     /// Returns a [`VersionConnect`] of `name:`[`VersionConnectName::KatcpLibrary`] for this rust library
+    #[cfg(feature = "std")]
     pub fn library() -> Self {
         let version = env!("CARGO_PKG_VERSION");
         let target = rustc_version::version().unwrap();
@@ -355,7 +451,32 @@ impl VersionConnect {
     }
 }
 
+#[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+/// Sent to the client on connect by KATCP v4 devices, which don't send [`VersionConnect`]. Receiving one means
+/// the device speaks [`ProtocolDialect::V4`].
+pub enum Version {
+    /// The version of the device's interface, e.g. `device-1.0`
+    Inform { version: String },
+}
+
+#[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+/// Sent to the client on connect by KATCP v4 devices along with [`Version`]
+pub enum BuildState {
+    /// The name and version of the device's software, e.g. `device-1.0.2rc1`
+    Inform { build_state: String },
+}
+
 #[derive(KatcpDiscrete, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// On specific [`InterfaceChanged`] informs, these specify how precisely the interface was changed
 pub enum ChangeSpecificationAction {
     Added,
@@ -363,7 +484,10 @@ pub enum ChangeSpecificationAction {
     Modified,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(KatcpArguments, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[katcp(tag = "type")]
 /// The sum type of the different [`InterfaceChanged`] informs
 pub enum InterfaceChangeInform {
     SensorList,
@@ -378,50 +502,9 @@ pub enum InterfaceChangeInform {
     },
 }
 
-impl ToKatcpArguments for InterfaceChangeInform {
-    fn to_arguments(&self) -> Vec<String> {
-        match self {
-            Self::SensorList => vec!["sensor-list".to_owned()],
-            Self::RequestList => vec!["request-list".to_owned()],
-            Self::Sensor { name, action } => vec![
-                "sensor".to_owned(),
-                name.to_argument(),
-                action.to_argument(),
-            ],
-            Self::Request { name, action } => vec![
-                "request".to_owned(),
-                name.to_argument(),
-                action.to_argument(),
-            ],
-        }
-    }
-}
-impl FromKatcpArguments for InterfaceChangeInform {
-    type Err = KatcpError;
-
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err> {
-        let inform_type = strings.next().ok_or(KatcpError::MissingArgument)?;
-        match inform_type.as_str() {
-            "sensor-list" => Ok(Self::SensorList),
-            "request-list" => Ok(Self::RequestList),
-            "sensor" => Ok(Self::Sensor {
-                name: String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?,
-                action: ChangeSpecificationAction::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
-            }),
-            "request" => Ok(Self::Request {
-                name: String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?,
-                action: ChangeSpecificationAction::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
-            }),
-            _ => Err(KatcpError::BadArgument),
-        }
-    }
-}
-
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Only required for dynamic devices, i.e. devices that may change their katcp interface during a connection.
 /// Sent to the client by the device to indicate that the katcp interface has changed. Passing no arguments
 /// with the inform implies that the whole katcp interface may have changed. The optional parameters allow
@@ -443,6 +526,11 @@ mod tests {
             ret_code: RetCode::Fail,
             message: "You Messed Up".to_owned(),
         }));
+        // An `ok` is only ever the `Ok` variant, so anything after it is surplus
+        assert!(matches!(
+            Halt::try_from("!halt ok extra"),
+            Err(KatcpError::ExtraArguments { .. })
+        ));
     }
 
     #[test]
@@ -456,6 +544,35 @@ mod tests {
             ret_code: RetCode::Fail,
             message: "Something went wrong".to_owned(),
         }));
+        assert!(Help::try_from("!help ok abc").is_err());
+    }
+
+    #[test]
+    fn test_help_metadata() {
+        assert_eq!("help", Help::NAME);
+        assert_eq!("version-connect", VersionConnect::NAME);
+        // The request's own docs are used if it has some, otherwise the message's
+        match Help::help() {
+            Help::Inform { name, description } => {
+                assert_eq!("help", name);
+                assert!(
+                    description.starts_with("Before sending a reply, the help request will send")
+                );
+                assert!(!description.contains('\n'));
+            }
+            help => panic!("expected an inform, got {:?}", help),
+        }
+        assert_eq!(
+            Watchdog::help(),
+            Help::Inform {
+                name: "watchdog".to_owned(),
+                description: "Requesting a watchdog may be sent by the client occasionally to check that \
+                              the connection to the device is still active. The device should respond with \
+                              a success reply if it receives the watchdog request"
+                    .to_owned(),
+            }
+        );
+        roundtrip_test(Restart::help());
     }
 
     #[test]
@@ -474,6 +591,40 @@ mod tests {
         roundtrip_test(Watchdog::Reply(GenericReply::Ok));
     }
 
+    #[test]
+    fn test_request_timeout_hint() {
+        roundtrip_test(RequestTimeoutHint::Request { name: None });
+        roundtrip_test(RequestTimeoutHint::Request {
+            name: Some("capture-init".to_owned()),
+        });
+        roundtrip_test(RequestTimeoutHint::Inform {
+            name: "capture-init".to_owned(),
+            timeout: Duration::from_millis(12_500),
+        });
+        roundtrip_test(RequestTimeoutHint::Inform {
+            name: "halt".to_owned(),
+            timeout: Duration::ZERO,
+        });
+        roundtrip_test(RequestTimeoutHint::Reply(IntReply::Ok { num: 2 }));
+        // The name is optional, so a bare request asks for all of the hints
+        assert_eq!(
+            RequestTimeoutHint::try_from("?request-timeout-hint").unwrap(),
+            RequestTimeoutHint::Request { name: None }
+        );
+        assert_eq!(
+            RequestTimeoutHint::try_from(r"?request-timeout-hint \@").unwrap(),
+            RequestTimeoutHint::Request { name: None }
+        );
+        assert_eq!(
+            RequestTimeoutHint::try_from("#request-timeout-hint capture-init 12.5").unwrap(),
+            RequestTimeoutHint::Inform {
+                name: "capture-init".to_owned(),
+                timeout: Duration::from_millis(12_500),
+            }
+        );
+        assert!(RequestTimeoutHint::try_from("#request-timeout-hint capture-init -1").is_err());
+    }
+
     #[test]
     fn test_version_list() {
         roundtrip_test(VersionList::Request);
@@ -498,19 +649,20 @@ mod tests {
 
     #[test]
     fn test_version_connect() {
+        #[cfg(feature = "std")]
         roundtrip_test(VersionConnect::library());
         roundtrip_test(VersionConnect::Inform(
             VersionConnectInform::KatcpProtocol {
                 major: 5,
                 minor: 1,
-                flags: HashSet::from([ProtocolFlags::MultiClient, ProtocolFlags::BulkSampling]),
+                flags: BTreeSet::from([ProtocolFlags::MultiClient, ProtocolFlags::BulkSampling]),
             },
         ));
         roundtrip_test(VersionConnect::Inform(
             VersionConnectInform::KatcpProtocol {
                 major: 5,
                 minor: 0,
-                flags: HashSet::new(),
+                flags: BTreeSet::new(),
             },
         ));
         roundtrip_test(VersionConnect::Inform(VersionConnectInform::Custom {
@@ -518,6 +670,24 @@ mod tests {
             version: "4.4.9-v7+".to_owned(),
             info: Some("#884 SMP Fri May 6 17:28:59 BST 2016".to_owned()),
         }));
+        // The reserved names have to fit their informs
+        assert!(VersionConnect::try_from("#version-connect katcp-protocol unknown").is_err());
+    }
+
+    #[test]
+    fn test_v4_connect_informs() {
+        roundtrip_test(Version::Inform {
+            version: "digitiser-2.1".to_owned(),
+        });
+        roundtrip_test(BuildState::Inform {
+            build_state: "digitiser-2.1.4-r1138".to_owned(),
+        });
+        assert_eq!(
+            BuildState::Inform {
+                build_state: "a b".to_owned()
+            },
+            BuildState::try_from(r"#build-state a\_b").unwrap()
+        );
     }
 
     #[test]
@@ -533,4 +703,33 @@ mod tests {
             action: ChangeSpecificationAction::Removed,
         }));
     }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let msg = VersionConnect::Inform(VersionConnectInform::KatcpDevice {
+            api_version: "1.0".to_owned(),
+            device: KatcpAddress::from_argument("192.168.1.2:7147").unwrap(),
+            build_state: "abc123".to_owned(),
+        });
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            r#"{"Inform":{"KatcpDevice":{"api_version":"1.0","device":"192.168.1.2:7147","build_state":"abc123"}}}"#,
+            json
+        );
+        assert_eq!(msg, serde_json::from_str(&json).unwrap());
+        let msg = VersionConnect::Inform(VersionConnectInform::KatcpProtocol {
+            major: 5,
+            minor: 1,
+            flags: BTreeSet::from([ProtocolFlags::MultiClient, ProtocolFlags::MessageIds]),
+        });
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""flags":["multi-client","message-ids"]"#));
+        assert_eq!(msg, serde_json::from_str(&json).unwrap());
+        assert_eq!(
+            r#""Request""#,
+            serde_json::to_string(&Halt::Request).unwrap()
+        );
+        assert!(serde_json::from_str::<KatcpAddress>(r#""not an address""#).is_err());
+    }
 }
@@ -7,11 +7,21 @@
 //!     .try_into()
 //!     .unwrap();
 //! ```
+use alloc::string::String;
+
 use katcp_derive::{KatcpDiscrete, KatcpMessage};
 
+#[cfg(feature = "arbitrary")]
+use crate::messages::common::arbitrary_fields;
 use crate::prelude::*;
 
 #[derive(KatcpDiscrete, Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Katcp log level, these match the typical log level heiarchy of log4j, syslog, etc
 pub enum Level {
     /// # Definition
@@ -70,6 +80,8 @@ pub enum Level {
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Messages for setting the device's log level
 pub enum LogLevel {
     Request { level: Level },
@@ -77,10 +89,17 @@ pub enum LogLevel {
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Log messages
 pub enum Log {
     Inform {
         level: Level,
+        #[cfg_attr(
+            feature = "serde",
+            serde(with = "crate::messages::common::serde_timestamp")
+        )]
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::timestamp))]
         timestamp: KatcpTimestamp,
         name: String,
         message: String,
@@ -89,6 +108,8 @@ pub enum Log {
 
 #[cfg(test)]
 mod tests {
+    use alloc::borrow::ToOwned;
+
     use chrono::{TimeZone, Utc};
 
     use super::*;
@@ -98,7 +119,7 @@ mod tests {
     fn test_log() {
         roundtrip_test(Log::Inform {
             level: Level::Error,
-            timestamp: Utc.timestamp(420, 3),
+            timestamp: Utc.timestamp_opt(420, 3).unwrap(),
             name: "foo.bar.baz".to_owned(),
             message: "This is a test message".to_owned(),
         });
@@ -108,4 +129,27 @@ mod tests {
         });
         roundtrip_test(LogLevel::Request { level: Level::Info });
     }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_log_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let err = Log::try_from(r"#log warm 10000 device.sub-system Something\_may\_be\_wrong")
+            .unwrap_err();
+        assert_eq!(
+            "bad argument `warm` for field `level` at argument 0 of `log`",
+            err.to_string()
+        );
+        let err = Log::try_from("#log warn 10000").unwrap_err();
+        assert_eq!(
+            "missing argument for field `name` at argument 2 of `log`",
+            err.to_string()
+        );
+        assert_eq!(
+            "expected a `log` message, found `log-level`",
+            Log::try_from("#log-level warn").unwrap_err().to_string()
+        );
+        // Errors work with `?`
+        let _: Log = "#log warn 10000 device.sub-system ok".try_into()?;
+        Ok(())
+    }
 }
@@ -4,3 +4,107 @@ pub mod core;
 pub mod log;
 pub mod multi_client;
 pub mod sensors;
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    };
+
+    use super::{core::*, log::*, multi_client::*, sensors::*};
+    use crate::prelude::*;
+
+    /// Decodes an empty message of every kind as `T`, checking the kinds `T` doesn't have are errors
+    fn decode_every_kind<T>(name: &'static str, supported: &[MessageKind])
+    where
+        T: TryFrom<Message, Error = KatcpError> + core::fmt::Debug,
+    {
+        for kind in [
+            MessageKind::Request,
+            MessageKind::Reply,
+            MessageKind::Inform,
+        ] {
+            let message = Message::new(kind, name, None, Vec::<String>::new()).unwrap();
+            let result = T::try_from(message);
+            if supported.contains(&kind) {
+                assert_ne!(
+                    result.err(),
+                    Some(KatcpError::UnsupportedKind { name, kind }),
+                    "{:?} should be supported by `{}`",
+                    kind,
+                    name
+                );
+            } else {
+                assert_eq!(result.unwrap_err(), KatcpError::UnsupportedKind {
+                    name,
+                    kind
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsupported_kinds() {
+        use MessageKind::*;
+
+        decode_every_kind::<Halt>("halt", &[Request, Reply]);
+        decode_every_kind::<Help>("help", &[Request, Reply, Inform]);
+        decode_every_kind::<Restart>("restart", &[Request, Reply]);
+        decode_every_kind::<Watchdog>("watchdog", &[Request, Reply]);
+        decode_every_kind::<VersionList>("version-list", &[Request, Reply, Inform]);
+        decode_every_kind::<Disconnect>("disconnect", &[Inform]);
+        decode_every_kind::<VersionConnect>("version-connect", &[Inform]);
+        decode_every_kind::<Version>("version", &[Inform]);
+        decode_every_kind::<BuildState>("build-state", &[Inform]);
+        decode_every_kind::<InterfaceChanged>("interface-changed", &[Inform]);
+        decode_every_kind::<RequestTimeoutHint>("request-timeout-hint", &[Request, Reply, Inform]);
+        decode_every_kind::<LogLevel>("log-level", &[Request, Reply]);
+        decode_every_kind::<Log>("log", &[Inform]);
+        decode_every_kind::<ClientList>("client-list", &[Request, Reply, Inform]);
+        decode_every_kind::<ClientConnected>("client-connected", &[Inform]);
+        decode_every_kind::<SensorList>("sensor-list", &[Request, Reply, Inform]);
+        decode_every_kind::<SensorSampling>("sensor-sampling", &[Request, Reply]);
+        decode_every_kind::<SensorSamplingClear>("sensor-sampling-clear", &[Request, Reply]);
+        decode_every_kind::<SensorValue>("sensor-value", &[Request, Reply, Inform]);
+        decode_every_kind::<SensorStatus>("sensor-status", &[Inform]);
+    }
+
+    #[test]
+    fn test_unsupported_kind_display() {
+        let error = Log::try_from("?log").unwrap_err();
+        assert_eq!(error.to_string(), "`?log` isn't a supported message");
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary() {
+        use crate::messages::common::roundtrip_property;
+
+        let properties: &[fn()] = &[
+            roundtrip_property::<Halt, _>,
+            roundtrip_property::<Help, _>,
+            roundtrip_property::<Restart, _>,
+            roundtrip_property::<Watchdog, _>,
+            roundtrip_property::<VersionList, _>,
+            roundtrip_property::<Disconnect, _>,
+            roundtrip_property::<VersionConnect, _>,
+            roundtrip_property::<Version, _>,
+            roundtrip_property::<BuildState, _>,
+            roundtrip_property::<InterfaceChanged, _>,
+            roundtrip_property::<RequestTimeoutHint, _>,
+            roundtrip_property::<LogLevel, _>,
+            roundtrip_property::<Log, _>,
+            roundtrip_property::<ClientList, _>,
+            roundtrip_property::<ClientConnected, _>,
+            roundtrip_property::<SensorList, _>,
+            roundtrip_property::<SensorSampling, _>,
+            roundtrip_property::<SensorSamplingClear, _>,
+            roundtrip_property::<SensorValue, _>,
+            roundtrip_property::<SensorStatus, _>,
+        ];
+        for property in properties {
+            property();
+        }
+    }
+}
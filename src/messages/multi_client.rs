@@ -25,11 +25,15 @@
 //! Devices should maintain one sensor sampling strategy per sensor per client and send sampled values only to
 //! the client that set up the sampling strategy.
 
+use alloc::string::String;
+
 use katcp_derive::KatcpMessage;
 
 use crate::prelude::*;
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// Messages for getting information about all the connected clients
 pub enum ClientList {
     /// Before sending a reply, the client-list request will send a client-list inform
@@ -43,6 +47,8 @@ pub enum ClientList {
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The inform messsage sent on new connections
 pub enum ClientConnected {
     /// A description of the new client. It should include the address and port the new client connected from
@@ -51,6 +57,8 @@ pub enum ClientConnected {
 
 #[cfg(test)]
 mod tests {
+    use alloc::borrow::ToOwned;
+
     use super::*;
     use crate::messages::common::roundtrip_test;
 
@@ -11,7 +11,7 @@
 //! use chrono::{TimeZone, Utc};
 //! use katcp::messages::sensors::{Sensor, SensorUpdates, SensorValue, Status};
 //!
-//! // Make a new sensor that is Sensor<f32> (implied from the type of the value)
+//! // Make a new sensor that is Sensor<f64> (implied from the type of the value)
 //! let mut pressure = Sensor::new("pump.pressure".to_owned(), Status::Unknown, Utc::now(), 0.0);
 //! // Then, we get a new message that contains the sensor update (implied try_into from the if let)
 //! let update = "#sensor-value 1427043968.954988 1 pump.pressure nominal 68.9"
@@ -23,31 +23,49 @@
 //!     readings,
 //! }) = update
 //! {
-//!     // This knows to serialize the reading into an f32 because of the type of the sensor
+//!     // This knows to serialize the reading into an f64 because of the type of the sensor
 //!     pressure
 //!         .update_from_reading(&timestamp, readings.first().unwrap())
 //!         .unwrap();
 //! }
+//! // The timestamp is exact, down to the nanosecond
+//! assert_eq!(
+//!     Utc.timestamp_opt(1427043968, 954988000).unwrap(),
+//!     pressure.last_updated()
+//! );
 //! // Sensor is now:
 //! // Sensor {
 //! //      name: "pump.pressure",
 //! //      status: Nominal,
-//! //      timestamp: 2015-03-22T17:06:08.954988002Z,
+//! //      timestamp: 2015-03-22T17:06:08.954988Z,
 //! //      value: 68.9,
 //! //  }
 //! ```
 
-use katcp_derive::{KatcpDiscrete, KatcpMessage};
+use alloc::{borrow::ToOwned, format, string::String, vec, vec::Vec};
+use core::time::Duration;
 
-use crate::{messages::common::from_argument_vec, prelude::*};
+use katcp_derive::{KatcpArguments, KatcpDiscrete, KatcpMessage};
+
+#[cfg(feature = "arbitrary")]
+use crate::messages::common::arbitrary_fields;
+use crate::{
+    messages::{common::next_argument, core::GenericReply},
+    prelude::*,
+};
 
 /// The core sensor type
 ///
 /// The value of a sensor is generic to anything that impls [`crate::messages::common::KatcpArgument`]
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sensor<T> {
     name: String,
     status: Status,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::messages::common::serde_timestamp")
+    )]
     timestamp: KatcpTimestamp,
     value: T,
 }
@@ -110,6 +128,12 @@ where
 
 /// The katcp sensor statuses
 #[derive(KatcpDiscrete, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Status {
     /// The sensor is in the process of being initialized and no value has yet been
     /// seen. Sensors should not remain in this state indefinitely.
@@ -142,7 +166,9 @@ impl Status {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(KatcpArguments, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The data of a [`SensorList`] inform message.
 /// You would use this information to design a [`Sensor`] type
 pub struct SensorListInform {
@@ -183,44 +209,14 @@ pub struct SensorListInform {
     ///
     /// ## Boolean, Timestamp, Address, String
     /// No additional parameters
+    #[katcp(flatten)]
     pub params: ArgumentVec,
 }
 
-impl ToKatcpArguments for SensorListInform {
-    fn to_arguments(&self) -> Vec<String> {
-        let mut prelude = vec![
-            self.name.to_argument(),
-            self.description.to_argument(),
-            self.units.to_argument(),
-        ];
-        prelude.push(self.params.to_string());
-        // Why oh why does append not return the result
-        prelude.append(&mut self.params.to_arguments());
-        prelude
-    }
-}
-
-impl FromKatcpArguments for SensorListInform {
-    type Err = KatcpError;
-
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err> {
-        let name = String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        let description =
-            String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        let units = String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        let ty = ArgumentType::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        let params = from_argument_vec(&ty, strings)?;
-        Ok(Self {
-            name,
-            description,
-            units,
-            params,
-        })
-    }
-}
-
 // Sensor Sampling
 #[derive(KatcpMessage, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The messages to query the available sensors
 pub enum SensorList {
     /// Before sending a reply, the sensor-list request will send a number of sensor-list inform messages. If no
@@ -230,13 +226,17 @@ pub enum SensorList {
     /// messages generated by this request. If the name parameter does not correspond to a sensor on the device,
     /// a fail reply should be sent.
     Request {
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::optional_name))]
         name: Option<String>,
     },
     Inform(SensorListInform),
     Reply(IntReply),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(KatcpArguments, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[katcp(tag = "strategy")]
 /// The sampling strategy (and associated params) for [`SensorSampling`]
 pub enum SamplingStrategy {
     /// Report the sensor value when convenient for
@@ -246,9 +246,9 @@ pub enum SamplingStrategy {
     /// Do not report the sensor value.
     None,
     /// Report the value approximately every period
-    /// seconds. The period will be specified using seconds as an f32.
+    /// seconds (milliseconds in KATCP v4).
     /// May be implementedmented for sensors of any type.
-    Period { period: f32 },
+    Period { period: Duration },
     /// Report the value whenever it changes. May
     /// be implemented for sensors of any type. For
     /// float sensors the device will have to determine
@@ -261,7 +261,10 @@ pub enum SamplingStrategy {
     /// sensors. The difference is formatted as a
     /// float for float sensors and an integer for
     /// integer sensors.
-    Differential { difference: f32 },
+    Differential {
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::float))]
+        difference: f64,
+    },
     /// Report the value whenever it changes or if
     /// more than longest-period seconds have
     /// passed since the last reported update. However,
@@ -271,8 +274,8 @@ pub enum SamplingStrategy {
     /// if shortest-period is greater than
     /// longest-period is undefined.
     EventRate {
-        shortest_period: f32,
-        longest_period: f32,
+        shortest_period: Duration,
+        longest_period: Duration,
     },
     /// Report the value whenever it changes by
     /// more than difference from the last reported
@@ -286,158 +289,194 @@ pub enum SamplingStrategy {
     /// and integer sensors. The difference is formatted
     /// as a float for float sensors and an integer for integer sensors.
     DifferentialRate {
-        difference: f32,
-        shortest_period: f32,
-        longest_period: f32,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::float))]
+        difference: f64,
+        shortest_period: Duration,
+        longest_period: Duration,
     },
 }
 
-impl ToKatcpArguments for SamplingStrategy {
-    fn to_arguments(&self) -> Vec<String> {
-        match self {
-            SamplingStrategy::Auto => vec!["auto".to_owned()],
-            SamplingStrategy::None => vec!["none".to_owned()],
-            SamplingStrategy::Period { period } => vec!["period".to_owned(), period.to_argument()],
-            SamplingStrategy::Event => vec!["event".to_owned()],
-            SamplingStrategy::Differential { difference } => {
-                vec!["differential".to_owned(), difference.to_argument()]
-            }
-            SamplingStrategy::EventRate {
-                shortest_period,
-                longest_period,
-            } => vec![
-                "event-rate".to_owned(),
-                shortest_period.to_argument(),
-                longest_period.to_argument(),
-            ],
-            SamplingStrategy::DifferentialRate {
-                difference,
-                shortest_period,
-                longest_period,
-            } => vec![
-                "differential-rate".to_owned(),
-                difference.to_argument(),
-                shortest_period.to_argument(),
-                longest_period.to_argument(),
-            ],
+/// The names of one or more sensors, which are sent as a comma-separated list. More than one name is only
+/// understood by servers with the `B` flag (see
+/// [`ProtocolFlags::BulkSampling`](crate::messages::core::ProtocolFlags::BulkSampling)).
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Vec<String>", into = "Vec<String>")
+)]
+pub struct SensorNames(Vec<String>);
+
+impl SensorNames {
+    /// Checks that there is at least one name, and that none of them are empty or contain a comma
+    pub fn new(names: Vec<String>) -> Result<Self, KatcpError> {
+        if names.is_empty()
+            || names
+                .iter()
+                .any(|name| name.is_empty() || name.contains(','))
+        {
+            return Err(KatcpError::bad_argument(names.join(",")));
         }
+        Ok(Self(names))
+    }
+
+    /// The name of a single sensor
+    pub fn single(name: impl Into<String>) -> Result<Self, KatcpError> {
+        Self::new(vec![name.into()])
+    }
+
+    /// Names getter
+    pub fn names(&self) -> &[String] {
+        &self.0
     }
 }
 
-impl FromKatcpArguments for SamplingStrategy {
-    type Err = KatcpError;
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, KatcpError> {
-        let strat = String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        Ok(match strat.as_str() {
-            "auto" => SamplingStrategy::Auto,
-            "none" => SamplingStrategy::None,
-            "period" => SamplingStrategy::Period {
-                period: f32::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?,
-            },
-            "event" => SamplingStrategy::Event,
-            "differential" => SamplingStrategy::Differential {
-                difference: f32::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?,
-            },
-            "event-rate" => SamplingStrategy::EventRate {
-                shortest_period: f32::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
-                longest_period: f32::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
-            },
-            "differential-rate" => SamplingStrategy::DifferentialRate {
-                difference: f32::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?,
-                shortest_period: f32::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
-                longest_period: f32::from_argument(
-                    strings.next().ok_or(KatcpError::MissingArgument)?,
-                )?,
-            },
-            _ => return Err(KatcpError::BadArgument),
-        })
+impl TryFrom<Vec<String>> for SensorNames {
+    type Error = KatcpError;
+
+    fn try_from(names: Vec<String>) -> Result<Self, Self::Error> {
+        Self::new(names)
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-/// The type representing a sensor sampling request
-pub struct SamplingRequest {
-    /// is the name of a single sensor. For bulk setting a comma-separated list of many sensor names can be used if the server supports the `B` flag
-    pub names: String,
-    /// pecifies a sampling strategy and is one of the strategies described in [`SamplingStrategy`]
-    /// If no strategy is
-    /// specified, the current strategy and parameters are left unchanged and just reported in the reply. This
-    /// querying of a strategy is only applicable when specifying a single sensor name, not a list of names.
-    pub strategy: Option<SamplingStrategy>,
+impl From<SensorNames> for Vec<String> {
+    fn from(names: SensorNames) -> Self {
+        names.0
+    }
 }
 
-impl ToKatcpArguments for SamplingRequest {
-    fn to_arguments(&self) -> Vec<String> {
-        let mut prelude = vec![self.names.to_argument()];
-        if let Some(strat) = &self.strategy {
-            prelude.append(&mut strat.to_arguments());
-            prelude
-        } else {
-            prelude
-        }
+impl ToKatcpArgument for SensorNames {
+    fn to_argument(&self) -> String {
+        self.0.join(",").to_argument()
     }
 }
 
-impl FromKatcpArguments for SamplingRequest {
+impl FromKatcpArgument for SensorNames {
     type Err = KatcpError;
 
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err> {
-        let names = String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        // If the next string is empty, we don't care, but a BadArgument is a real error we want to send up
-        match SamplingStrategy::from_arguments(strings) {
-            Ok(strategy) => Ok(Self {
-                names,
-                strategy: Some(strategy),
-            }),
-            Err(e) => match e {
-                e @ KatcpError::BadArgument => Err(e),
-                _ => Ok(Self {
-                    names,
-                    strategy: None,
-                }),
-            },
+    fn from_argument(s: impl AsRef<str>) -> Result<Self, Self::Err> {
+        let joined = String::from_argument(s.as_ref())?;
+        Self::new(joined.split(',').map(str::to_owned).collect())
+            .map_err(|_| KatcpError::bad_argument(s.as_ref()))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for SensorNames {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let len = u.int_in_range(1..=8)?;
+        let names = (0..len).map(|_| u.arbitrary()).collect::<Result<_, _>>()?;
+        Self::new(names).map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+}
+
+#[derive(KatcpArguments, Debug, PartialEq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "UncheckedSamplingRequest")
+)]
+#[katcp(validate = "SamplingRequest::validate")]
+/// The type representing a sensor sampling request, see [`SamplingRequest::new`]
+pub struct SamplingRequest {
+    names: SensorNames,
+    #[katcp(flatten, skip_if_none)]
+    strategy: Option<SamplingStrategy>,
+}
+
+impl SamplingRequest {
+    /// Sets the sampling strategy of the sensors in `names` to `strategy`, one of the strategies described in
+    /// [`SamplingStrategy`]. If no strategy is given, the current strategy and parameters are left unchanged and
+    /// just reported in the reply. That querying of a strategy is only applicable to a single sensor, so this fails
+    /// for a query of more than one.
+    pub fn new(names: SensorNames, strategy: Option<SamplingStrategy>) -> Result<Self, KatcpError> {
+        let request = Self { names, strategy };
+        request.validate()?;
+        Ok(request)
+    }
+
+    /// Names getter
+    pub fn names(&self) -> &SensorNames {
+        &self.names
+    }
+
+    /// Strategy getter, `None` for a query of the current strategy
+    pub fn strategy(&self) -> Option<&SamplingStrategy> {
+        self.strategy.as_ref()
+    }
+
+    fn validate(&self) -> Result<(), KatcpError> {
+        if self.strategy.is_none() && self.names.names().len() > 1 {
+            return Err(KatcpError::bad_argument(self.names.to_argument()).in_field("names"));
         }
+        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-/// The Reply type for [`SensorSampling`]
-pub struct SamplingReply {
-    pub names: String,
-    pub strategy: SamplingStrategy,
+/// The fields of a [`SamplingRequest`] before they've been checked, for deserializing it
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UncheckedSamplingRequest {
+    names: SensorNames,
+    strategy: Option<SamplingStrategy>,
 }
 
-impl ToKatcpArguments for SamplingReply {
-    fn to_arguments(&self) -> Vec<String> {
-        let mut prelude = vec![self.names.to_argument()];
-        prelude.append(&mut self.strategy.to_arguments());
-        prelude
+#[cfg(feature = "serde")]
+impl TryFrom<UncheckedSamplingRequest> for SamplingRequest {
+    type Error = KatcpError;
+
+    fn try_from(request: UncheckedSamplingRequest) -> Result<Self, Self::Error> {
+        Self::new(request.names, request.strategy)
     }
 }
-impl FromKatcpArguments for SamplingReply {
-    type Err = KatcpError;
 
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err> {
-        let names = String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        let strategy = SamplingStrategy::from_arguments(strings)?;
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for SamplingRequest {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let strategy: Option<SamplingStrategy> = u.arbitrary()?;
+        let names = match strategy {
+            Some(_) => u.arbitrary()?,
+            // Only a single sensor can be queried
+            None => SensorNames::single(u.arbitrary::<String>()?)
+                .map_err(|_| arbitrary::Error::IncorrectFormat)?,
+        };
         Ok(Self { names, strategy })
     }
 }
 
+#[derive(KatcpArguments, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+/// The Reply type for [`SensorSampling`]
+pub struct SamplingReply {
+    /// The names from the request
+    pub names: SensorNames,
+    #[katcp(flatten)]
+    pub strategy: SamplingStrategy,
+}
+
 #[derive(KatcpMessage, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The messages that control how sensors are sampled
 pub enum SensorSampling {
     Request(SamplingRequest),
     Reply(SamplingReply),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+/// The messages that stop all of the sensor sampling of a client
+pub enum SensorSamplingClear {
+    /// Sets the sampling strategy of every sensor to none for the client making the request
+    Request,
+    Reply(GenericReply),
+}
+
+#[derive(KatcpArguments, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// A complete sensor reading, returned by [`SensorValue`] and [`SensorStatus`]
 pub struct SensorReading {
     pub name: String,
@@ -447,34 +486,16 @@ pub struct SensorReading {
     pub value: String,
 }
 
-impl FromKatcpArguments for SensorReading {
-    type Err = KatcpError;
-
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err> {
-        let name = String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        let status = Status::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        let value = String::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        Ok(Self {
-            name,
-            status,
-            value,
-        })
-    }
-}
-
-impl ToKatcpArguments for SensorReading {
-    fn to_arguments(&self) -> Vec<String> {
-        vec![
-            self.name.to_argument(),
-            self.status.to_argument(),
-            self.value.to_argument(),
-        ]
-    }
-}
-
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// A timestamped collection of [`SensorReading`]s
 pub struct SensorUpdates {
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::messages::common::serde_timestamp")
+    )]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::timestamp))]
     pub timestamp: KatcpTimestamp,
     pub readings: Vec<SensorReading>,
 }
@@ -482,13 +503,14 @@ pub struct SensorUpdates {
 impl FromKatcpArguments for SensorUpdates {
     type Err = KatcpError;
 
-    fn from_arguments(strings: &mut impl Iterator<Item = String>) -> Result<Self, Self::Err> {
-        let timestamp =
-            KatcpTimestamp::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
-        let num_sensors = u32::from_argument(strings.next().ok_or(KatcpError::MissingArgument)?)?;
+    fn from_arguments<A: AsRef<[u8]>>(
+        arguments: &mut ArgumentDecoder<'_, A>,
+    ) -> Result<Self, Self::Err> {
+        let timestamp = next_argument(arguments, "timestamp")?;
+        let num_sensors: u32 = next_argument(arguments, "num_sensors")?;
         let mut readings = vec![];
         for _ in 1..=num_sensors {
-            readings.push(SensorReading::from_arguments(strings)?);
+            readings.push(arguments.decode_group()?);
         }
         Ok(Self {
             timestamp,
@@ -499,22 +521,21 @@ impl FromKatcpArguments for SensorUpdates {
 
 impl ToKatcpArguments for SensorUpdates {
     fn to_arguments(&self) -> Vec<String> {
-        let mut prelude = vec![
-            self.timestamp.to_argument(),
-            (self.readings.len() as u32).to_argument(),
-        ];
-        prelude.append(
-            &mut self
-                .readings
-                .iter()
-                .flat_map(|r| r.to_arguments())
-                .collect(),
-        );
-        prelude
+        ArgumentEncoder::collect(|encoder| self.encode_arguments(encoder))
+    }
+
+    fn encode_arguments<E>(&self, encoder: &mut ArgumentEncoder<'_, E>) -> Result<(), E> {
+        encoder.encode(&self.timestamp)?;
+        encoder.encode(&(self.readings.len() as u32))?;
+        self.readings
+            .iter()
+            .try_for_each(|reading| encoder.encode_group(reading))
     }
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The messages involving directly querying a sensor's value
 pub enum SensorValue {
     /// Before sending a reply, the sensor-value request will send a number of sensor-value inform messages. If
@@ -524,6 +545,7 @@ pub enum SensorValue {
     /// the number of inform messages generated by this request. If the name parameter does not correspond to
     /// a sensor on the device, a fail reply should be sent.
     Request {
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_fields::optional_name))]
         name: Option<String>,
     },
     Reply(IntReply),
@@ -534,6 +556,8 @@ pub enum SensorValue {
 }
 
 #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The async sensor status update message
 pub enum SensorStatus {
     /// A sensor-status inform should be sent whenever the sensor sampling set up by the client dictates. The
@@ -545,10 +569,12 @@ pub enum SensorStatus {
 
 #[cfg(test)]
 mod sensor_tests {
+    use alloc::string::ToString;
+
     use chrono::{TimeZone, Utc};
 
     use super::*;
-    use crate::messages::common::roundtrip_test;
+    use crate::{messages::common::roundtrip_test, protocol::ArgumentContext};
 
     #[test]
     fn test_sensor() {
@@ -591,45 +617,235 @@ mod sensor_tests {
         }));
     }
 
+    #[test]
+    fn test_sensor_list_errors() {
+        let err = SensorList::try_from(r"#sensor-list rfe0.temperature Temp K float 1.0 bad")
+            .unwrap_err();
+        assert_eq!(
+            KatcpError::BadArgument {
+                raw: "bad".to_owned(),
+                context: ArgumentContext {
+                    message: Some("sensor-list"),
+                    field: Some("params"),
+                    index: Some(5),
+                },
+            },
+            err
+        );
+        assert_eq!(
+            "bad argument `bad` for field `params` at argument 5 of `sensor-list`",
+            err.to_string()
+        );
+        let err = SensorList::try_from(r"#sensor-list rfe0.temperature Temp").unwrap_err();
+        assert_eq!(
+            Some(&ArgumentContext {
+                message: Some("sensor-list"),
+                field: Some("units"),
+                index: Some(2),
+            }),
+            err.context()
+        );
+        let err = SensorList::try_from(r"#sensor-list rfe0.temperature Temp K floaty").unwrap_err();
+        assert_eq!(
+            Some(&ArgumentContext {
+                message: Some("sensor-list"),
+                field: Some("type"),
+                index: Some(3),
+            }),
+            err.context()
+        );
+        // The params lead with their type
+        assert_eq!(
+            vec!["integer", "0", "1"],
+            ArgumentVec::Integer(vec![0, 1]).to_arguments()
+        );
+    }
+
+    fn sampling_request(names: &[&str], strategy: Option<SamplingStrategy>) -> SensorSampling {
+        let names = SensorNames::new(names.iter().map(|&name| name.to_owned()).collect()).unwrap();
+        SensorSampling::Request(SamplingRequest::new(names, strategy).unwrap())
+    }
+
     #[test]
     fn test_sensor_sampling() {
-        roundtrip_test(SensorSampling::Request(SamplingRequest {
-            names: "wind-speed".to_owned(),
-            strategy: Some(SamplingStrategy::Auto),
-        }));
-        roundtrip_test(SensorSampling::Request(SamplingRequest {
-            names: "wind-speed".to_owned(),
-            strategy: Some(SamplingStrategy::None),
-        }));
-        roundtrip_test(SensorSampling::Request(SamplingRequest {
-            names: "wind-speed".to_owned(),
-            strategy: None,
-        }));
-        roundtrip_test(SensorSampling::Request(SamplingRequest {
-            names: "wind-speed".to_owned(),
-            strategy: Some(SamplingStrategy::Period { period: 1.0 }),
-        }));
-        roundtrip_test(SensorSampling::Request(SamplingRequest {
-            names: "wind-speed".to_owned(),
-            strategy: Some(SamplingStrategy::DifferentialRate {
+        roundtrip_test(sampling_request(
+            &["wind-speed"],
+            Some(SamplingStrategy::Auto),
+        ));
+        roundtrip_test(sampling_request(
+            &["wind-speed"],
+            Some(SamplingStrategy::None),
+        ));
+        roundtrip_test(sampling_request(&["wind-speed"], None));
+        roundtrip_test(sampling_request(
+            &["wind-speed"],
+            Some(SamplingStrategy::Period {
+                period: Duration::from_secs(1),
+            }),
+        ));
+        roundtrip_test(sampling_request(
+            &["wind-speed"],
+            Some(SamplingStrategy::DifferentialRate {
                 difference: 10.5,
-                shortest_period: 3.1,
-                longest_period: 15.0,
+                shortest_period: Duration::from_millis(3100),
+                longest_period: Duration::from_secs(15),
             }),
-        }));
+        ));
         roundtrip_test(SensorSampling::Reply(SamplingReply {
-            names: "wind-speed".to_owned(),
+            names: SensorNames::single("wind-speed").unwrap(),
             strategy: SamplingStrategy::EventRate {
-                shortest_period: 3.15,
-                longest_period: 2.71,
+                shortest_period: Duration::from_millis(3150),
+                longest_period: Duration::from_millis(2710),
             },
         }));
         roundtrip_test(SensorSampling::Reply(SamplingReply {
-            names: "wind-speed".to_owned(),
+            names: SensorNames::single("wind-speed").unwrap(),
             strategy: SamplingStrategy::Differential { difference: 420.69 },
         }));
     }
 
+    #[test]
+    fn test_bulk_sampling() {
+        let request =
+            sampling_request(&["wind speed", "rain.gauge"], Some(SamplingStrategy::Event));
+        assert_eq!(
+            "?sensor-sampling wind\\_speed,rain.gauge event\n",
+            request.to_message(None).unwrap().to_string()
+        );
+        roundtrip_test(request);
+        roundtrip_test(SensorSampling::Reply(SamplingReply {
+            names: SensorNames::new(vec!["wind speed".to_owned(), "rain.gauge".to_owned()])
+                .unwrap(),
+            strategy: SamplingStrategy::Period {
+                period: Duration::from_millis(500),
+            },
+        }));
+        // A strategy can only be queried for a single sensor, whether it's being built or decoded
+        let names =
+            SensorNames::new(vec!["wind.speed".to_owned(), "rain.gauge".to_owned()]).unwrap();
+        assert!(SamplingRequest::new(names, None).is_err());
+        assert_eq!(
+            SensorSampling::try_from("?sensor-sampling wind.speed,rain.gauge")
+                .unwrap_err()
+                .context()
+                .and_then(|context| context.field),
+            Some("names")
+        );
+        for bad in [
+            "?sensor-sampling , auto",
+            "?sensor-sampling a,,b auto",
+            "?sensor-sampling",
+        ] {
+            assert!(SensorSampling::try_from(bad).is_err(), "{}", bad);
+        }
+        // The names themselves can't be empty or contain commas
+        assert!(SensorNames::new(vec![]).is_err());
+        assert!(SensorNames::single("").is_err());
+        assert!(SensorNames::single("a,b").is_err());
+    }
+
+    #[test]
+    fn test_sensor_sampling_clear() {
+        roundtrip_test(SensorSamplingClear::Request);
+        roundtrip_test(SensorSamplingClear::Reply(GenericReply::Ok));
+        roundtrip_test(SensorSamplingClear::Reply(GenericReply::Error {
+            ret_code: RetCode::Fail,
+            message: "Nothing to clear".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn test_v4_sensors() {
+        use crate::messages::common::ProtocolDialect::{self, V4};
+
+        let v4 = "#sensor-status 1427043968954 1 pump.pressure nominal 68.9";
+        let status = SensorStatus::from_dialect_message(v4.try_into().unwrap(), V4).unwrap();
+        assert_eq!(
+            SensorStatus::Inform(SensorUpdates {
+                timestamp: Utc.timestamp_opt(1427043968, 954000000).unwrap(),
+                readings: vec![SensorReading {
+                    name: "pump.pressure".to_owned(),
+                    status: Status::Nominal,
+                    value: "68.9".to_owned(),
+                }],
+            }),
+            status
+        );
+        assert_eq!(
+            format!("{}\n", v4),
+            status.to_dialect_message(None, V4).unwrap().to_string()
+        );
+        assert_eq!(
+            "#sensor-status 1427043968.954 1 pump.pressure nominal 68.9\n",
+            status
+                .to_dialect_message(None, ProtocolDialect::V5)
+                .unwrap()
+                .to_string()
+        );
+        // The params of timestamp sensors are timestamps too
+        let list = SensorList::Inform(SensorListInform {
+            name: "gps.time".to_owned(),
+            description: "GPS time".to_owned(),
+            units: "".to_owned(),
+            params: ArgumentVec::Timestamp(vec![
+                Utc.timestamp_opt(0, 0).unwrap(),
+                Utc.timestamp_opt(1, 500_000_000).unwrap(),
+            ]),
+        });
+        let message = list.to_dialect_message(None, V4).unwrap();
+        assert_eq!(
+            "#sensor-list gps.time GPS\\_time \\@ timestamp 0 1500\n",
+            message.to_string()
+        );
+        assert_eq!(list, SensorList::from_dialect_message(message, V4).unwrap());
+        // Other messages and kinds are unchanged
+        let request = SensorValue::Request {
+            name: Some("1500".to_owned()),
+        };
+        assert_eq!(
+            request.to_message(None).unwrap(),
+            request.to_dialect_message(None, V4).unwrap()
+        );
+        let err = SensorValue::from_dialect_message(
+            "#sensor-value 1427043968.954.1 1 pump.pressure nominal 68.9"
+                .try_into()
+                .unwrap(),
+            V4,
+        )
+        .unwrap_err();
+        assert_eq!(
+            Some(&ArgumentContext {
+                message: Some("sensor-value"),
+                field: Some("timestamp"),
+                index: Some(0),
+            }),
+            err.context()
+        );
+        // Sampling periods are in milliseconds
+        let sampling = SensorSampling::Reply(SamplingReply {
+            names: SensorNames::single("pump.pressure").unwrap(),
+            strategy: SamplingStrategy::EventRate {
+                shortest_period: Duration::from_millis(250),
+                longest_period: Duration::from_secs(10),
+            },
+        });
+        let v4 = "!sensor-sampling pump.pressure event-rate 250 10000";
+        assert_eq!(
+            format!("{}\n", v4),
+            sampling.to_dialect_message(None, V4).unwrap().to_string()
+        );
+        assert_eq!(
+            sampling,
+            SensorSampling::from_dialect_message(v4.try_into().unwrap(), V4).unwrap()
+        );
+        #[cfg(feature = "std")]
+        {
+            let mut written = vec![];
+            sampling.write_dialect_to(None, V4, &mut written).unwrap();
+            assert_eq!(format!("{}\n", v4).into_bytes(), written);
+        }
+    }
+
     #[test]
     fn test_sensor_value() {
         roundtrip_test(SensorValue::Request { name: None });
@@ -642,7 +858,7 @@ mod sensor_tests {
             message: "Uh oh".to_owned(),
         }));
         roundtrip_test(SensorValue::Inform(SensorUpdates {
-            timestamp: Utc.timestamp(1654553033, 0),
+            timestamp: Utc.timestamp_opt(1654553033, 0).unwrap(),
             readings: vec![
                 SensorReading {
                     name: "big-fat-motor.current".to_owned(),
@@ -679,7 +895,7 @@ mod sensor_tests {
             readings,
         }) = incoming_message
         {
-            // This knows to serialize the reading into an f32 because of the type of the sensor
+            // This knows to serialize the reading into an f64 because of the type of the sensor
             pump_pressure
                 .update_from_reading(&timestamp, readings.first().unwrap())
                 .unwrap();
@@ -690,10 +906,50 @@ mod sensor_tests {
         }
     }
 
+    #[test]
+    fn test_wide_sensors() {
+        let reading = |name: &str, value: &str| SensorReading {
+            name: name.to_owned(),
+            status: Status::Nominal,
+            value: value.to_owned(),
+        };
+        let mut packets = Sensor::new("fpga.packets".to_owned(), Status::Unknown, Utc::now(), 0u64);
+        packets
+            .update_from_reading(
+                &Utc::now(),
+                &reading("fpga.packets", "18446744073709551615"),
+            )
+            .unwrap();
+        assert_eq!(u64::MAX, packets.value());
+        let mut frequency = Sensor::new(
+            "fpga.frequency".to_owned(),
+            Status::Unknown,
+            Utc::now(),
+            0f64,
+        );
+        frequency
+            .update_from_reading(&Utc::now(), &reading("fpga.frequency", "1420405751.7667"))
+            .unwrap();
+        assert_eq!(1420405751.7667, frequency.value());
+        roundtrip_test(SensorList::Inform(SensorListInform {
+            name: "fpga.packets".to_owned(),
+            description: "Packets received".to_owned(),
+            units: "".to_owned(),
+            params: ArgumentVec::Integer(vec![0, i64::MAX]),
+        }));
+        roundtrip_test(SensorSampling::Request(
+            SamplingRequest::new(
+                SensorNames::single("fpga.frequency").unwrap(),
+                Some(SamplingStrategy::Differential { difference: 1e-9 }),
+            )
+            .unwrap(),
+        ));
+    }
+
     #[test]
     fn test_sensor_status() {
         roundtrip_test(SensorStatus::Inform(SensorUpdates {
-            timestamp: Utc.timestamp(1654553033, 0),
+            timestamp: Utc.timestamp_opt(1654553033, 0).unwrap(),
             readings: vec![
                 SensorReading {
                     name: "big-fat-motor.current".to_owned(),
@@ -708,4 +964,45 @@ mod sensor_tests {
             ],
         }));
     }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let msg = SensorStatus::Inform(SensorUpdates {
+            timestamp: Utc.timestamp_opt(1657500000, 500_000_000).unwrap(),
+            readings: vec![SensorReading {
+                name: "pump.pressure".to_owned(),
+                status: Status::Warn,
+                value: "90000.0".to_owned(),
+            }],
+        });
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            r#"{"Inform":{"timestamp":"2022-07-11T00:40:00.500Z","readings":[{"name":"pump.pressure","status":"warn","value":"90000.0"}]}}"#,
+            json
+        );
+        assert_eq!(msg, serde_json::from_str(&json).unwrap());
+        let msg = SensorList::Inform(SensorListInform {
+            name: "drive.enable-local".to_owned(),
+            description: "Drive enabled".to_owned(),
+            units: "".to_owned(),
+            params: ArgumentVec::Timestamp(vec![Utc.timestamp_opt(0, 0).unwrap()]),
+        });
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""params":{"Timestamp":["1970-01-01T00:00:00Z"]}"#));
+        assert_eq!(msg, serde_json::from_str(&json).unwrap());
+        let msg = sampling_request(&["a", "b"], Some(SamplingStrategy::Event));
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            r#"{"Request":{"names":["a","b"],"strategy":"Event"}}"#,
+            json
+        );
+        assert_eq!(msg, serde_json::from_str(&json).unwrap());
+        // Deserializing is checked just like building
+        assert!(serde_json::from_str::<SensorSampling>(
+            r#"{"Request":{"names":["a","b"],"strategy":null}}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<SensorNames>(r#"["a,b"]"#).is_err());
+    }
 }
@@ -3,14 +3,19 @@
 //! This is useful to `use katcp::prelude::*;` to satisfy all the imports
 //! for deriving `KatcpMessage` from the `katcp_derive` trait
 
+#[doc(hidden)]
+pub use crate::{__katcp_write_to, messages::common::__encode_message};
 pub use crate::{
     messages::{
         common::{
-            ArgumentType, ArgumentVec, FromKatcpArgument, FromKatcpArguments, KatcpAddress,
-            KatcpArgument, KatcpMessage, KatcpTimestamp, RetCode, ToKatcpArgument,
-            ToKatcpArguments,
+            ArgumentDecoder, ArgumentEncoder, ArgumentType, ArgumentVec, FromKatcpArgument,
+            FromKatcpArguments, KatcpAddress, KatcpArgument, KatcpMessage, KatcpTimestamp,
+            ProtocolDialect, RetCode, ToKatcpArgument, ToKatcpArguments,
         },
         core::IntReply,
     },
-    protocol::{KatcpError, Message, MessageKind, MessageResult},
+    protocol::{
+        KatcpError, Message, MessageBytes, MessageId, MessageKind, MessageRef, MessageResult,
+        RawMessage,
+    },
 };
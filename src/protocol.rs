@@ -22,6 +22,15 @@
 //! let msg_b = Message::from_str(msg_str).unwrap();
 //! ```
 //!
+//! If your peer sends arguments that aren't valid UTF-8 (binary blobs, Latin-1 text, etc.), parse into a [`MessageBytes`]
+//! instead, either with `TryFrom<&[u8]>` or the [`message_bytes`] parser.
+//!
+//! If you are reading from a socket, use the [`MessageDecoder`] which buffers partial lines and only yields complete messages.
+//! Message ids are [`MessageId`]s, which can't be 0. To correlate requests and replies, hand them out with an [`IdAllocator`].
+//!
+//! Conversions from strings are [`ParseMode::Strict`]. Some devices send CRLF line endings, blank keep-alive lines or
+//! `[0]` ids; use [`Message::parse`] (or [`MessageDecoder::with_mode`]) with [`ParseMode::Lenient`] to accept those.
+//!
 //! If you are working on a stream of messages, you can invoke the parser directly. The parser is written with the [nom](https://github.com/Geal/nom)
 //! parser combinator library, so the top level [`message`] can be used with that directly.
 //!
@@ -40,6 +49,8 @@
 //! Note: the serialization function does *not* check validity, that is performed with the standard [`Message::new`]
 //! consstructor. The `Display` methods will assume a constructed message is valid. If you want to skip these validation steps
 //! there is the [`Message::new_unchecked`], which is marked `unsafe`.
+//! To build an ad-hoc message from typed arguments (with the escaping taken care of), use the [`MessageBuilder`] from
+//! [`Message::request`], [`Message::reply`] or [`Message::inform`].
 //!
 //! ```
 //! use katcp::protocol::{Message, MessageKind};
@@ -50,21 +61,34 @@
 //! .unwrap(); // Panic on bad arguments
 //! let msg_str = format!("{}", msg);
 //! ```
+//!
+//! `Display` doesn't allocate, so writing into any `fmt::Write` is cheap. For sockets and other `io::Write`rs, use
+//! [`Message::write_to`] (or `KatcpMessage::write_to` for the typed messages, which skips the [`Message`] entirely).
 
-use core::{fmt::Display, str::FromStr};
+use alloc::{collections::BTreeSet, string::String, vec, vec::Vec};
+use core::{convert::Infallible, fmt::Display, num::NonZeroU32, str::FromStr};
+#[cfg(feature = "std")]
+use std::io;
 
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alpha1, alphanumeric1, char, digit0, none_of, one_of},
-    combinator::{eof, map_res, opt, recognize},
-    error::Error,
+    combinator::{eof, map, map_res, opt, recognize, value},
     multi::{many0, many1},
     sequence::{delimited, pair, preceded, tuple},
     IResult,
 };
 
+use crate::messages::common::ToKatcpArgument;
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 /// The kind of katcp message. The docs call this the type, but we want to scoot
 /// around the fact that `type` is a reserved keyword.
 pub enum MessageKind {
@@ -76,7 +100,250 @@ pub enum MessageKind {
     Inform,
 }
 
+impl MessageKind {
+    /// The character that starts a message of this kind
+    pub fn symbol(&self) -> char {
+        match self {
+            MessageKind::Request => '?',
+            MessageKind::Reply => '!',
+            MessageKind::Inform => '#',
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u32", into = "u32")
+)]
+/// The id of a message, used to match replies (and their informs) to requests. The spec restricts these to
+/// `1..=u32::MAX`, which this type enforces.
+pub struct MessageId(NonZeroU32);
+
+impl MessageId {
+    /// The smallest valid id
+    pub const MIN: Self = Self(unsafe { NonZeroU32::new_unchecked(1) });
+    /// The largest valid id
+    pub const MAX: Self = Self(unsafe { NonZeroU32::new_unchecked(u32::MAX) });
+
+    /// Creates an id, or `None` if `id` is 0
+    pub const fn new(id: u32) -> Option<Self> {
+        match NonZeroU32::new(id) {
+            Some(id) => Some(Self(id)),
+            None => None,
+        }
+    }
+
+    /// The id as a primitive
+    pub const fn get(self) -> u32 {
+        self.0.get()
+    }
+
+    /// The id after this one, wrapping from [`MessageId::MAX`] back around to [`MessageId::MIN`]
+    pub fn wrapping_next(self) -> Self {
+        Self::new(self.get().wrapping_add(1)).unwrap_or(Self::MIN)
+    }
+}
+
+impl TryFrom<u32> for MessageId {
+    type Error = KatcpError;
+
+    fn try_from(id: u32) -> Result<Self, Self::Error> {
+        Self::new(id).ok_or(KatcpError::InvalidId(id))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for MessageId {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self::new(u.int_in_range(1..=u32::MAX)?).unwrap())
+    }
+}
+
+impl From<NonZeroU32> for MessageId {
+    fn from(id: NonZeroU32) -> Self {
+        Self(id)
+    }
+}
+
+impl From<MessageId> for u32 {
+    fn from(id: MessageId) -> Self {
+        id.get()
+    }
+}
+
+impl Display for MessageId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Hands out [`MessageId`]s for outgoing requests so their replies can be matched up
+///
+/// Ids are handed out in increasing order, wrapping back around to 1 after `u32::MAX`. An id is "in flight" from
+/// when it's allocated until it's [released](IdAllocator::release), and ids in flight are skipped when wrapping.
+///
+/// ```
+/// use katcp::protocol::{IdAllocator, MessageId};
+///
+/// let mut ids = IdAllocator::new();
+/// let first = ids.allocate().unwrap();
+/// assert_eq!(MessageId::new(1), Some(first));
+/// // Once the reply to `first` shows up
+/// assert!(ids.release(first));
+/// ```
+#[derive(Debug, Clone)]
+pub struct IdAllocator {
+    /// The next id to try
+    next: MessageId,
+    /// The ids that have been allocated and not released
+    in_flight: BTreeSet<MessageId>,
+}
+
+impl Default for IdAllocator {
+    fn default() -> Self {
+        Self::starting_at(MessageId::MIN)
+    }
+}
+
+impl IdAllocator {
+    /// Creates an allocator that starts at 1
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an allocator whose first id will be `next` (or the one after, if that's not available)
+    pub fn starting_at(next: MessageId) -> Self {
+        Self {
+            next,
+            in_flight: BTreeSet::new(),
+        }
+    }
+
+    /// Allocates the next available id, or `None` if every id is in flight
+    pub fn allocate(&mut self) -> Option<MessageId> {
+        if self.in_flight.len() >= u32::MAX as usize {
+            return None;
+        }
+        let mut id = self.next;
+        while self.in_flight.contains(&id) {
+            id = id.wrapping_next();
+        }
+        self.in_flight.insert(id);
+        self.next = id.wrapping_next();
+        Some(id)
+    }
+
+    /// Marks `id` as no longer in flight, so it can be allocated again. Returns `false` if it wasn't in flight.
+    pub fn release(&mut self, id: MessageId) -> bool {
+        self.in_flight.remove(&id)
+    }
+
+    /// Whether `id` has been allocated and not yet released
+    pub fn is_in_flight(&self, id: MessageId) -> bool {
+        self.in_flight.contains(&id)
+    }
+
+    /// The number of ids in flight
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+}
+
+#[cfg(test)]
+mod id_tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn test_message_id() {
+        assert_eq!(None, MessageId::new(0));
+        assert_eq!(Some(42), MessageId::new(42).map(u32::from));
+        assert_eq!(MessageId::MIN, MessageId::MAX.wrapping_next());
+        assert_eq!(MessageId::new(2), Some(MessageId::MIN.wrapping_next()));
+        assert_eq!(Err(KatcpError::InvalidId(0)), MessageId::try_from(0));
+        assert_eq!("4294967295", MessageId::MAX.to_string());
+        assert_eq!(
+            MessageId::new(7),
+            Message::request("watchdog")
+                .id(MessageId::new(7).unwrap())
+                .build()
+                .unwrap()
+                .id()
+        );
+    }
+
+    #[test]
+    fn test_allocator() {
+        let mut ids = IdAllocator::new();
+        let a = ids.allocate().unwrap();
+        let b = ids.allocate().unwrap();
+        assert_eq!((1, 2), (a.get(), b.get()));
+        assert!(ids.is_in_flight(a));
+        assert!(ids.release(a));
+        assert!(!ids.release(a));
+        assert!(!ids.is_in_flight(a));
+        assert_eq!(1, ids.in_flight());
+        assert_eq!(3, ids.allocate().unwrap().get());
+    }
+
+    #[test]
+    fn test_allocator_wrapping() {
+        let mut ids = IdAllocator::starting_at(MessageId::new(u32::MAX - 1).unwrap());
+        assert_eq!(u32::MAX - 1, ids.allocate().unwrap().get());
+        assert_eq!(MessageId::MAX, ids.allocate().unwrap());
+        assert_eq!(MessageId::MIN, ids.allocate().unwrap());
+        assert!(ids.release(MessageId::MAX));
+        assert_eq!(2, ids.allocate().unwrap().get());
+        assert!(ids.is_in_flight(MessageId::new(u32::MAX - 1).unwrap()));
+        assert!(!ids.is_in_flight(MessageId::MAX));
+        assert_eq!(3, ids.in_flight());
+    }
+
+    #[test]
+    #[ignore = "allocates every id, run it with `cargo test --release -- --ignored`"]
+    fn test_allocator_skips_in_flight() {
+        let mut ids = IdAllocator::new();
+        let held = [ids.allocate().unwrap(), ids.allocate().unwrap()];
+        // Go all the way around, so the next ids are the ones allocated first
+        for _ in 2..u32::MAX {
+            let id = ids.allocate().unwrap();
+            ids.release(id);
+        }
+        assert!(ids.release(held[0]));
+        assert_eq!(held[0], ids.allocate().unwrap());
+        // The other is still in flight, so it's skipped
+        assert_eq!(3, ids.allocate().unwrap().get());
+        assert!(ids.is_in_flight(held[1]));
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+/// How strictly to follow the spec when parsing messages
+pub enum ParseMode {
+    /// Only accept messages exactly as described by the spec
+    Strict,
+    /// Accept (and normalise) quirks of real-world devices:
+    /// - Leading whitespace and blank (keep-alive) lines are skipped
+    /// - CRLF line endings are treated as a single line ending
+    /// - A message id of `[0]` is treated as no id at all
+    Lenient,
+}
+
+impl Default for ParseMode {
+    fn default() -> Self {
+        Self::Strict
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "MessageFields")
+)]
 /// The core raw message type of katcp
 pub struct Message {
     /// The message kind
@@ -84,7 +351,7 @@ pub struct Message {
     /// The message name
     pub(crate) name: String,
     /// The optional, positive message id
-    pub(crate) id: Option<u32>,
+    pub(crate) id: Option<MessageId>,
     /// The (potentially empty) vector of message arguments
     /// In this structure, these will always be strings. It
     /// is left to consumers to define the serde into the
@@ -92,19 +359,268 @@ pub struct Message {
     pub(crate) arguments: Vec<String>,
 }
 
+/// The unvalidated fields of a deserialized [`Message`]
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct MessageFields {
+    kind: MessageKind,
+    name: String,
+    id: Option<MessageId>,
+    arguments: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<MessageFields> for Message {
+    type Error = KatcpError;
+
+    fn try_from(fields: MessageFields) -> Result<Self, Self::Error> {
+        Message::new(fields.kind, fields.name, fields.id, fields.arguments)
+    }
+}
+
+/// Generates valid messages: the name follows the grammar and the arguments are arbitrary strings, escaped
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Message {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        const NAME_CHARS: &[u8] =
+            b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-";
+        let kind = u.arbitrary()?;
+        let mut name = String::from(*u.choose(LETTERS)? as char);
+        for _ in 0..u.arbitrary_len::<u8>()? {
+            name.push(*u.choose(NAME_CHARS)? as char);
+        }
+        let id = u.arbitrary()?;
+        let arguments = Vec::<String>::arbitrary(u)?
+            .iter()
+            .map(|argument| argument.to_argument())
+            .collect();
+        Ok(Self {
+            kind,
+            name,
+            id,
+            arguments,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// A borrowed version of [`Message`] whose name and arguments point into the buffer it was parsed from
+///
+/// This avoids allocating a `String` per argument, which matters when handling many messages (e.g. high-rate
+/// sensor updates). The arguments are still escaped, exactly as they appeared on the wire.
+///
+/// ```
+/// use katcp::{
+///     messages::core::Watchdog,
+///     protocol::{Message, MessageRef},
+/// };
+///
+/// let msg: MessageRef = "?watchdog[2]".try_into().unwrap();
+/// assert_eq!(msg.name(), "watchdog");
+/// // Convert to a typed message directly
+/// let watchdog: Watchdog = msg.clone().try_into().unwrap();
+/// // Or into an owned message
+/// let owned: Message = msg.into();
+/// ```
+pub struct MessageRef<'a> {
+    /// The message kind
+    pub(crate) kind: MessageKind,
+    /// The message name
+    pub(crate) name: &'a str,
+    /// The optional, positive message id
+    pub(crate) id: Option<MessageId>,
+    /// The (potentially empty) vector of raw, escaped message arguments
+    pub(crate) arguments: Vec<&'a str>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+/// Where in a message an argument error occurred. This is filled in as the error propagates up through the decoders,
+/// so the innermost (most specific) information wins.
+pub struct ArgumentContext {
+    /// The name of the message being decoded
+    pub message: Option<&'static str>,
+    /// The name of the field being decoded
+    pub field: Option<&'static str>,
+    /// The (zero-based) index of the argument in the message
+    pub index: Option<usize>,
+}
+
+impl Display for ArgumentContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(field) = self.field {
+            write!(f, " for field `{}`", field)?;
+        }
+        if let Some(index) = self.index {
+            write!(f, " at argument {}", index)?;
+        }
+        if let Some(message) = self.message {
+            write!(f, " of `{}`", message)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, PartialEq)]
 /// The core Error type for this crate
 pub enum KatcpError {
-    ParseError(nom::Err<Error<String>>),
-    BadArgument,
-    MissingArgument,
-    IncorrectType,
+    /// The input wasn't a valid katcp message
+    ParseError(ParseError),
+    /// An argument was present, but couldn't be decoded into the type we wanted
+    BadArgument {
+        /// The raw (escaped) text of the argument
+        raw: String,
+        context: ArgumentContext,
+    },
+    /// An argument we needed wasn't there
+    MissingArgument {
+        context: ArgumentContext,
+    },
+    /// There were more arguments than the type we were decoding takes. The context's index is the first surplus one
+    ExtraArguments {
+        /// How many arguments were decoded
+        expected: usize,
+        /// How many arguments the message had
+        found: usize,
+        context: ArgumentContext,
+    },
+    /// Tried to decode a message of one name into the type for a different message
+    IncorrectType {
+        expected: &'static str,
+        found: String,
+    },
+    /// Tried to decode a message into a type that doesn't have its kind, e.g. a `?log` request
+    UnsupportedKind {
+        name: &'static str,
+        kind: MessageKind,
+    },
+    /// A message id was out of range (the spec only allows `1..=u32::MAX`)
+    InvalidId(u32),
+    /// A line was longer than the limit (in bytes) a [`MessageDecoder`] was configured with
+    LineTooLong(usize),
     Message(String),
     Unknown,
 }
 
 pub type MessageResult = Result<Message, KatcpError>;
 
+impl KatcpError {
+    /// Constructs a [`KatcpError::BadArgument`] with no context
+    pub fn bad_argument(raw: impl Into<String>) -> Self {
+        Self::BadArgument {
+            raw: raw.into(),
+            context: ArgumentContext::default(),
+        }
+    }
+
+    /// Constructs a [`KatcpError::MissingArgument`] with no context
+    pub fn missing_argument() -> Self {
+        Self::MissingArgument {
+            context: ArgumentContext::default(),
+        }
+    }
+
+    /// Constructs a [`KatcpError::ExtraArguments`] with no context
+    pub fn extra_arguments(expected: usize, found: usize) -> Self {
+        Self::ExtraArguments {
+            expected,
+            found,
+            context: ArgumentContext::default(),
+        }
+    }
+
+    /// The context of an argument error, if this is one
+    pub fn context(&self) -> Option<&ArgumentContext> {
+        match self {
+            Self::BadArgument { context, .. }
+            | Self::MissingArgument { context }
+            | Self::ExtraArguments { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    fn context_mut(&mut self) -> Option<&mut ArgumentContext> {
+        match self {
+            Self::BadArgument { context, .. }
+            | Self::MissingArgument { context }
+            | Self::ExtraArguments { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Records the name of the message being decoded, if it isn't already known
+    pub fn in_message(mut self, name: &'static str) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.message.get_or_insert(name);
+        }
+        self
+    }
+
+    /// Records the name of the field being decoded, if it isn't already known
+    pub fn in_field(mut self, name: &'static str) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.field.get_or_insert(name);
+        }
+        self
+    }
+
+    /// Records the index of the argument being decoded, if it isn't already known
+    pub fn at_index(mut self, index: usize) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.index.get_or_insert(index);
+        }
+        self
+    }
+}
+
+impl Display for KatcpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            KatcpError::ParseError(e) => write!(f, "{}", e),
+            KatcpError::BadArgument { raw, context } => {
+                write!(f, "bad argument `{}`{}", raw, context)
+            }
+            KatcpError::MissingArgument { context } => write!(f, "missing argument{}", context),
+            KatcpError::ExtraArguments {
+                expected,
+                found,
+                context,
+            } => write!(
+                f,
+                "too many arguments, expected at most {} but found {}{}",
+                expected, found, context
+            ),
+            KatcpError::IncorrectType { expected, found } => {
+                write!(f, "expected a `{}` message, found `{}`", expected, found)
+            }
+            KatcpError::UnsupportedKind { name, kind } => {
+                write!(f, "`{}{}` isn't a supported message", kind.symbol(), name)
+            }
+            KatcpError::InvalidId(id) => {
+                write!(
+                    f,
+                    "invalid message id {}, ids are between 1 and 4294967295",
+                    id
+                )
+            }
+            KatcpError::LineTooLong(limit) => {
+                write!(f, "line is longer than the limit of {} bytes", limit)
+            }
+            KatcpError::Message(s) => write!(f, "{}", s),
+            KatcpError::Unknown => write!(f, "unknown error"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KatcpError {}
+
+impl From<ParseError> for KatcpError {
+    fn from(e: ParseError) -> Self {
+        Self::ParseError(e)
+    }
+}
+
 impl Message {
     /// A constructor for message that will create owned copies of the string-slice arguments
     /// # Safety
@@ -114,7 +630,7 @@ impl Message {
     pub unsafe fn new_unchecked<T: AsRef<str>, U: AsRef<str>>(
         kind: MessageKind,
         name: T,
-        id: Option<u32>,
+        id: Option<MessageId>,
         arguments: Vec<U>,
     ) -> Self {
         Self {
@@ -129,23 +645,34 @@ impl Message {
     pub fn new<T: AsRef<str>, U: AsRef<str>>(
         kind: MessageKind,
         name: T,
-        id: Option<u32>,
+        id: Option<MessageId>,
         arguments: Vec<U>,
     ) -> Result<Self, KatcpError> {
-        // Check name
-        if let Err(e) = crate::protocol::name(name.as_ref()) {
-            return Err(KatcpError::ParseError(own_nom_err(e)));
-        }
-        for argument in arguments.iter() {
-            if let Err(e) = crate::protocol::argument(argument.as_ref()) {
-                return Err(KatcpError::ParseError(own_nom_err(e)));
-            }
+        ParseError::validate_name(name.as_ref())?;
+        for (index, argument) in arguments.iter().enumerate() {
+            ParseError::validate_argument(index, argument.as_ref().as_bytes())?;
         }
         // Safety: this is after we've thrown parser results for validation of name
         // and arguments, so we're good to go here
         unsafe { Ok(Self::new_unchecked(kind, name, id, arguments)) }
     }
 
+    /// Parses `input` as a single message (with an optional line ending), following the rules of `mode`
+    ///
+    /// ```
+    /// use katcp::protocol::{Message, ParseMode};
+    ///
+    /// let quirky = "  ?set-rate[0] 4.1\r\n";
+    /// assert!(Message::parse(quirky, ParseMode::Strict).is_err());
+    /// assert_eq!(
+    ///     Message::parse(quirky, ParseMode::Lenient).unwrap(),
+    ///     Message::parse("?set-rate 4.1\n", ParseMode::Strict).unwrap()
+    /// );
+    /// ```
+    pub fn parse(input: &str, mode: ParseMode) -> MessageResult {
+        MessageRef::parse(input, mode).map(Self::from)
+    }
+
     /// Kind getter
     pub fn kind(&self) -> MessageKind {
         self.kind
@@ -157,7 +684,7 @@ impl Message {
     }
 
     /// Id getter
-    pub fn id(&self) -> Option<u32> {
+    pub fn id(&self) -> Option<MessageId> {
         self.id
     }
 
@@ -165,158 +692,630 @@ impl Message {
     pub fn arguments(&self) -> Vec<String> {
         self.arguments.clone()
     }
-}
 
-fn own_nom_err(e: nom::Err<Error<&str>>) -> nom::Err<Error<String>> {
-    match e {
-        nom::Err::Incomplete(i) => nom::Err::Incomplete(i),
-        nom::Err::Error(Error { input, code }) => nom::Err::Error(Error {
-            input: input.to_owned(),
-            code,
-        }),
-        nom::Err::Failure(Error { input, code }) => nom::Err::Failure(Error {
-            input: input.to_owned(),
-            code,
-        }),
+    /// Starts building a request message with the given name, see [`MessageBuilder`]
+    pub fn request(name: impl Into<String>) -> MessageBuilder {
+        MessageBuilder::new(MessageKind::Request, name)
     }
-}
 
-fn kind(input: &str) -> IResult<&str, MessageKind> {
-    let (remaining, typ) = one_of("!#?")(input)?;
-    Ok((remaining, match typ {
-        '?' => MessageKind::Request,
-        '!' => MessageKind::Reply,
-        '#' => MessageKind::Inform,
-        _ => unreachable!(),
-    }))
-}
+    /// Starts building a reply message with the given name, see [`MessageBuilder`]
+    pub fn reply(name: impl Into<String>) -> MessageBuilder {
+        MessageBuilder::new(MessageKind::Reply, name)
+    }
 
-fn whitespace(input: &str) -> IResult<&str, &str> {
-    recognize(many1(one_of(" \t")))(input)
+    /// Starts building an inform message with the given name, see [`MessageBuilder`]
+    pub fn inform(name: impl Into<String>) -> MessageBuilder {
+        MessageBuilder::new(MessageKind::Inform, name)
+    }
 }
 
-fn name(input: &str) -> IResult<&str, &str> {
-    recognize(pair(alpha1, many0(alt((alphanumeric1, tag("-"))))))(input)
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// A builder for ad-hoc [`Message`]s, where the arguments are anything that implements [`ToKatcpArgument`]
+///
+/// Arguments are escaped as they are added and everything is validated in [`MessageBuilder::build`], so there's no
+/// need for manual escaping or [`Message::new_unchecked`].
+///
+/// ```
+/// use katcp::protocol::{Message, MessageId};
+///
+/// let msg = Message::request("set-rate")
+///     .id(MessageId::new(5).unwrap())
+///     .arg(&4.1f32)
+///     .arg("some text")
+///     .build()
+///     .unwrap();
+/// assert_eq!("?set-rate[5] 4.1 some\\_text\n", msg.to_string());
+/// ```
+pub struct MessageBuilder {
+    kind: MessageKind,
+    name: String,
+    id: Option<MessageId>,
+    arguments: Vec<Vec<u8>>,
 }
 
-fn id(input: &str) -> IResult<&str, u32> {
-    map_res(
-        delimited(
-            char('['),
-            recognize(tuple((one_of("123456789"), digit0))),
-            char(']'),
-        ),
-        str::parse,
-    )(input)
-}
+impl MessageBuilder {
+    /// Starts building a message of `kind` with the given name and no id or arguments
+    pub fn new(kind: MessageKind, name: impl Into<String>) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            id: None,
+            arguments: vec![],
+        }
+    }
 
-fn escape(input: &str) -> IResult<&str, &str> {
-    recognize(pair(char('\\'), one_of("\\_0nret@")))(input)
+    /// Sets the message id
+    pub fn id(mut self, id: MessageId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Appends an argument, serialized with [`ToKatcpArgument::to_argument_bytes`]
+    pub fn arg<T: ToKatcpArgument + ?Sized>(mut self, argument: &T) -> Self {
+        self.arguments.push(argument.to_argument_bytes());
+        self
+    }
+
+    /// Appends every argument from `arguments` in order
+    pub fn args<'a, T: ToKatcpArgument + ?Sized + 'a>(
+        self,
+        arguments: impl IntoIterator<Item = &'a T>,
+    ) -> Self {
+        arguments.into_iter().fold(self, Self::arg)
+    }
+
+    /// Validates the name and arguments, producing the finished [`Message`]. As a [`Message`] holds `String`s, this
+    /// fails on any argument that isn't valid UTF-8 rather than losing its bytes.
+    pub fn build(self) -> MessageResult {
+        ParseError::validate_name(&self.name)?;
+        let arguments = self
+            .arguments
+            .into_iter()
+            .enumerate()
+            .map(|(index, argument)| {
+                ParseError::validate_argument(index, &argument)?;
+                String::from_utf8(argument).map_err(|e| {
+                    KatcpError::bad_argument(String::from_utf8_lossy(e.as_bytes())).at_index(index)
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Message {
+            kind: self.kind,
+            name: self.name,
+            id: self.id,
+            arguments,
+        })
+    }
 }
 
-fn eol(input: &str) -> IResult<&str, &str> {
-    recognize(one_of("\n\r"))(input)
+impl<'a> MessageRef<'a> {
+    /// Parses `input` as a single message (with an optional line ending), following the rules of `mode`
+    pub fn parse(input: &'a str, mode: ParseMode) -> Result<Self, KatcpError> {
+        MessageBytes::parse(input.as_bytes(), mode)?.try_into()
+    }
+
+    /// Kind getter
+    pub fn kind(&self) -> MessageKind {
+        self.kind
+    }
+
+    /// Name getter
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Id getter
+    pub fn id(&self) -> Option<MessageId> {
+        self.id
+    }
+
+    /// Arguments getter
+    pub fn arguments(&self) -> &[&'a str] {
+        &self.arguments
+    }
 }
 
-fn plain(input: &str) -> IResult<&str, &str> {
-    recognize(many1(none_of("\\ \0\n\r\t")))(input)
+impl From<MessageRef<'_>> for Message {
+    fn from(msg: MessageRef<'_>) -> Self {
+        // Safety: a `MessageRef` either came from the parser or from a valid `Message`
+        unsafe { Message::new_unchecked(msg.kind, msg.name, msg.id, msg.arguments) }
+    }
 }
 
-fn argument(input: &str) -> IResult<&str, &str> {
-    recognize(many1(alt((escape, plain))))(input)
+impl<'a> From<&'a Message> for MessageRef<'a> {
+    fn from(msg: &'a Message) -> Self {
+        Self {
+            kind: msg.kind,
+            name: &msg.name,
+            id: msg.id,
+            arguments: msg.arguments.iter().map(String::as_str).collect(),
+        }
+    }
 }
 
-/// The parser combinator for messages. One could write a grammar that utilizes this parser with nom.
-pub fn message(input: &str) -> IResult<&str, Message> {
-    let (remaining, (kind, name, id, arguments, _, _)) = tuple((
-        kind,
-        name,
-        opt(id),
-        many0(preceded(whitespace, argument)),
-        opt(whitespace),
-        alt((eol, eof)),
-    ))(input)?;
-
-    // Safety: this is after we've unwrapped the parser result, so any parser errors will have been
-    // thrown already, so we can guarantee that this message will be valid
-    Ok((remaining, unsafe {
-        Message::new_unchecked(kind, name, id, arguments)
-    }))
+/// The parts of a raw message that typed messages are decoded from. This lets the derived decoders read a [`Message`],
+/// [`MessageRef`] or [`MessageBytes`] in place, without converting between them.
+pub trait RawMessage {
+    /// How the raw, escaped arguments are stored
+    type Argument: AsRef<[u8]>;
+
+    fn kind(&self) -> MessageKind;
+
+    fn name(&self) -> &str;
+
+    fn arguments(&self) -> &[Self::Argument];
 }
 
-#[cfg(test)]
-mod parser_tests {
-    use super::*;
+impl RawMessage for Message {
+    type Argument = String;
 
-    #[test]
-    fn test_msg_type() {
-        assert_eq!(Ok(("", MessageKind::Reply)), kind("!"));
-        assert_eq!(Ok(("", MessageKind::Inform)), kind("#"));
-        assert_eq!(Ok(("", MessageKind::Request)), kind("?"));
+    fn kind(&self) -> MessageKind {
+        self.kind
     }
 
-    #[test]
-    fn test_name() {
-        assert_eq!(Ok(("", "set-rate")), name("set-rate"));
-        assert_eq!(Ok(("", "foobar")), name("foobar"));
-        assert_eq!(Ok(("", "f00-bar")), name("f00-bar"));
+    fn name(&self) -> &str {
+        &self.name
     }
 
-    #[test]
-    fn test_msg_id() {
-        assert_eq!(Ok(("", 123)), id("[123]"));
-        assert_eq!(Ok(("", 100)), id("[100]"));
-        assert_eq!(Ok(("", 9)), id("[9]"));
+    fn arguments(&self) -> &[String] {
+        &self.arguments
     }
+}
 
-    #[test]
-    fn test_whitespace() {
-        assert_eq!(Ok(("", " ")), whitespace(" "));
-        assert_eq!(Ok(("", "    ")), whitespace("    "));
-        assert_eq!(Ok(("", "\t    \t")), whitespace("\t    \t"));
+impl<'a> RawMessage for MessageRef<'a> {
+    type Argument = &'a str;
+
+    fn kind(&self) -> MessageKind {
+        self.kind
     }
 
-    #[test]
-    fn test_escaped() {
-        assert_eq!(Ok(("", r"\\")), escape(r"\\"));
-        assert_eq!(Ok(("", r"\_")), escape(r"\_"));
-        assert_eq!(Ok(("", r"\0")), escape(r"\0"));
-        assert_eq!(Ok(("", r"\n")), escape(r"\n"));
-        assert_eq!(Ok(("", r"\r")), escape(r"\r"));
-        assert_eq!(Ok(("", r"\e")), escape(r"\e"));
-        assert_eq!(Ok(("", r"\t")), escape(r"\t"));
-        assert_eq!(Ok(("", r"\@")), escape(r"\@"));
+    fn name(&self) -> &str {
+        self.name
     }
 
-    #[test]
-    fn test_eol() {
-        assert_eq!(Ok(("", "\n")), eol("\n"));
-        assert_eq!(Ok(("", "\r")), eol("\r"));
+    fn arguments(&self) -> &[&'a str] {
+        &self.arguments
     }
+}
 
-    #[test]
-    fn test_plain() {
-        assert_eq!(Ok(("", "6.1")), plain("6.1"));
-        assert_eq!(Ok(("", "invalid")), plain("invalid"));
-        assert_eq!(Ok(("\\_request.", "Unknown")), plain("Unknown\\_request."));
+impl<'a> RawMessage for MessageBytes<'a> {
+    type Argument = &'a [u8];
+
+    fn kind(&self) -> MessageKind {
+        self.kind
     }
 
-    #[test]
-    fn test_argument() {
-        assert_eq!(Ok(("", "6.1")), argument("6.1"));
-        assert_eq!(Ok(("", "invalid")), argument("invalid"));
-        assert_eq!(
-            Ok(("", "Unknown\\_request.")),
-            argument("Unknown\\_request.")
-        );
+    fn name(&self) -> &str {
+        self.name
     }
 
-    #[test]
-    fn test_message() {
-        assert_eq!(
-            Message::new(MessageKind::Request, "set-rate", None, vec!["5.1"]).unwrap(),
-            message("?set-rate 5.1").unwrap().1
-        );
+    fn arguments(&self) -> &[&'a [u8]] {
+        &self.arguments
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+/// The part of a message that a [`ParseError`] occurred in
+pub enum Component {
+    /// The leading `?`, `!` or `#`
+    Kind,
+    /// The message name
+    Name,
+    /// The bracketed message id
+    Id,
+    /// The argument with the given (zero-based) index
+    Argument(usize),
+    /// The end of the message
+    LineEnding,
+}
+
+impl Display for Component {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Component::Kind => write!(f, "message kind"),
+            Component::Name => write!(f, "message name"),
+            Component::Id => write!(f, "message id"),
+            Component::Argument(index) => write!(f, "argument {}", index),
+            Component::LineEnding => write!(f, "line ending"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// A description of where and why some input failed to parse as a katcp message
+///
+/// The `Display` impl renders the offending line with a caret under the failure:
+/// ```
+/// use katcp::protocol::{Component, KatcpError, Message};
+///
+/// let err = r"?set-rate[123] 4.1 \q".parse::<Message>().unwrap_err();
+/// if let KatcpError::ParseError(e) = err {
+///     assert_eq!(e.component(), Component::Argument(1));
+///     assert_eq!(e.column(), 20);
+///     assert_eq!(
+///         e.to_string(),
+///         "expected a valid escape sequence (one of \\\\, \\_, \\0, \\n, \\r, \\e, \\t or \\@) in argument 1 at column 20
+/// ?set-rate[123] 4.1 \\q
+///                    ^"
+///     );
+/// }
+/// ```
+pub struct ParseError {
+    /// The line that failed to parse, without its line ending
+    line: String,
+    /// The byte offset into `line` of the failure
+    offset: usize,
+    /// The component that failed to parse
+    component: Component,
+    /// A human-readable description of what we expected to find at `offset`
+    expected: &'static str,
+}
+
+impl ParseError {
+    fn new(line: &[u8], offset: usize, component: Component, expected: &'static str) -> Self {
+        let end = line
+            .iter()
+            .position(|b| *b == b'\n' || *b == b'\r')
+            .unwrap_or(line.len());
+        let offset = offset.min(end);
+        // Bytes that aren't valid UTF-8 become a (three byte) U+FFFD, so convert either side of
+        // the failure separately to keep the offset pointing at the same place in `line`
+        let mut lossy = String::from_utf8_lossy(&line[..offset]).into_owned();
+        let lossy_offset = lossy.len();
+        lossy.push_str(&String::from_utf8_lossy(&line[offset..end]));
+        Self {
+            line: lossy,
+            offset: lossy_offset,
+            component,
+            expected,
+        }
+    }
+
+    /// Works out where and why `input` failed to parse as a message
+    fn diagnose(input: &[u8], mode: ParseMode) -> Self {
+        let err = |rest: &[u8], component, expected| {
+            Self::new(input, input.len() - rest.len(), component, expected)
+        };
+        let rest = match kind(input) {
+            Ok((rest, _)) => rest,
+            Err(_) => return err(input, Component::Kind, "one of ?, ! or #"),
+        };
+        let rest = match name(rest) {
+            Ok((rest, _)) => rest,
+            Err(_) => return err(rest, Component::Name, "a letter"),
+        };
+        let (mut rest, mut last) = if rest.first() == Some(&b'[') {
+            match message_id(mode)(rest) {
+                Ok((rest, _)) => (rest, Component::Id),
+                Err(_) => {
+                    return err(
+                        rest,
+                        Component::Id,
+                        "an id between 1 and 4294967295 in square brackets",
+                    )
+                }
+            }
+        } else {
+            (rest, Component::Name)
+        };
+        let mut index = 0;
+        loop {
+            // Every component has to be followed by whitespace or the end of the line
+            rest = match whitespace(rest) {
+                Ok((rest, _)) => rest,
+                Err(_) => {
+                    return match (rest.first(), last) {
+                        (None | Some(b'\n' | b'\r'), _) => {
+                            err(rest, Component::LineEnding, "a valid message")
+                        }
+                        (_, Component::Name) => err(rest, last, "a letter, digit or -"),
+                        (_, Component::Argument(_)) => err(rest, last, argument_expectation(rest)),
+                        _ => err(rest, Component::LineEnding, "whitespace or a line ending"),
+                    }
+                }
+            };
+            if let None | Some(b'\n' | b'\r') = rest.first() {
+                return err(rest, Component::LineEnding, "a valid message");
+            }
+            last = Component::Argument(index);
+            rest = match argument(rest) {
+                Ok((rest, _)) => rest,
+                Err(_) => return err(rest, last, argument_expectation(rest)),
+            };
+            index += 1;
+        }
+    }
+
+    /// Checks that `name` is a complete, valid message name
+    fn validate_name(name: &str) -> Result<(), Self> {
+        match self::name(name.as_bytes()) {
+            Ok(([], _)) => Ok(()),
+            Ok((rest, _)) => Err(Self::new(
+                name.as_bytes(),
+                name.len() - rest.len(),
+                Component::Name,
+                "a letter, digit or -",
+            )),
+            Err(_) => Err(Self::new(name.as_bytes(), 0, Component::Name, "a letter")),
+        }
+    }
+
+    /// Checks that `argument` is a complete, valid (escaped) argument
+    fn validate_argument(index: usize, argument: &[u8]) -> Result<(), Self> {
+        let rest = match self::argument(argument) {
+            Ok(([], _)) => return Ok(()),
+            Ok((rest, _)) => rest,
+            Err(_) => argument,
+        };
+        Err(Self::new(
+            argument,
+            argument.len() - rest.len(),
+            Component::Argument(index),
+            argument_expectation(rest),
+        ))
+    }
+
+    /// The line that failed to parse, without its line ending
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// The byte offset into [`ParseError::line`] where parsing failed
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The (one-based) column of [`ParseError::line`] where parsing failed
+    pub fn column(&self) -> usize {
+        self.line[..self.offset].chars().count() + 1
+    }
+
+    /// The part of the message that failed to parse
+    pub fn component(&self) -> Component {
+        self.component
+    }
+
+    /// A human-readable description of what the parser expected to find
+    pub fn expected(&self) -> &'static str {
+        self.expected
+    }
+}
+
+/// What an argument should contain, given the `rest` of the argument that failed to parse
+fn argument_expectation(rest: &[u8]) -> &'static str {
+    match rest.first() {
+        None => r"a non-empty argument (an empty argument is written as \@)",
+        Some(b'\\') => r"a valid escape sequence (one of \\, \_, \0, \n, \r, \e, \t or \@)",
+        Some(b'\0') => r"an escaped null (\0)",
+        Some(b' ' | b'\t') => r"an escaped space (\_) or tab (\t)",
+        _ => r"an escaped line ending (\n or \r)",
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(
+            f,
+            "expected {} in {} at column {}",
+            self.expected,
+            self.component,
+            self.column()
+        )?;
+        writeln!(f, "{}", self.line)?;
+        // Keep tabs so the caret lines up with the line above
+        for c in self.line[..self.offset].chars() {
+            f.write_str(if c == '\t' { "\t" } else { " " })?;
+        }
+        f.write_str("^")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+// The grammar works on bytes, as arguments can hold anything other than the characters that must be escaped. The
+// `&str` parsers are thin wrappers around it.
+
+fn kind(input: &[u8]) -> IResult<&[u8], MessageKind> {
+    let (remaining, typ) = one_of("!#?")(input)?;
+    Ok((remaining, match typ {
+        '?' => MessageKind::Request,
+        '!' => MessageKind::Reply,
+        '#' => MessageKind::Inform,
+        _ => unreachable!(),
+    }))
+}
+
+fn whitespace(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(many1(one_of(" \t")))(input)
+}
+
+fn name(input: &[u8]) -> IResult<&[u8], &str> {
+    map_res(
+        recognize(pair(alpha1, many0(alt((alphanumeric1, tag("-")))))),
+        core::str::from_utf8,
+    )(input)
+}
+
+fn id(input: &[u8]) -> IResult<&[u8], MessageId> {
+    map(
+        map_res(
+            map_res(
+                delimited(
+                    char('['),
+                    recognize(tuple((one_of("123456789"), digit0))),
+                    char(']'),
+                ),
+                core::str::from_utf8,
+            ),
+            str::parse,
+        ),
+        MessageId,
+    )(input)
+}
+
+/// A bracketed message id. In lenient mode `[0]` is accepted too and means there's no id.
+fn message_id(mode: ParseMode) -> impl FnMut(&[u8]) -> IResult<&[u8], Option<MessageId>> {
+    move |input| match mode {
+        ParseMode::Strict => map(id, Some)(input),
+        ParseMode::Lenient => alt((map(id, Some), value(None, tag("[0]"))))(input),
+    }
+}
+
+fn escape(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(pair(char('\\'), one_of("\\_0nret@")))(input)
+}
+
+fn eol(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(one_of("\n\r"))(input)
+}
+
+fn plain(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(many1(none_of("\\ \0\n\r\t")))(input)
+}
+
+fn argument(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(many1(alt((escape, plain))))(input)
+}
+
+/// The message grammar, with the quirks of `mode` within a line
+fn message_in(mode: ParseMode) -> impl FnMut(&[u8]) -> IResult<&[u8], MessageBytes<'_>> {
+    move |input| {
+        let (remaining, (kind, name, id, arguments, _, _)) = tuple((
+            kind,
+            name,
+            map(opt(message_id(mode)), Option::flatten),
+            many0(preceded(whitespace, argument)),
+            opt(whitespace),
+            alt((eol, eof)),
+        ))(input)?;
+        Ok((remaining, MessageBytes {
+            kind,
+            name,
+            id,
+            arguments,
+        }))
+    }
+}
+
+/// Skips any leading whitespace and blank lines
+fn trim_blank(input: &[u8]) -> &[u8] {
+    let start = input
+        .iter()
+        .position(|b| !b" \t\r\n".contains(b))
+        .unwrap_or(input.len());
+    &input[start..]
+}
+
+/// The parser combinator for messages over bytes, where arguments may contain any byte outside of the escape set
+pub fn message_bytes(input: &[u8]) -> IResult<&[u8], MessageBytes<'_>> {
+    message_in(ParseMode::Strict)(input)
+}
+
+/// The parser combinator for borrowed messages. This is [`message_bytes`] for input that's known to be UTF-8, so the
+/// name and arguments of the resulting [`MessageRef`] point into `input` instead of being copied.
+pub fn message_ref(input: &str) -> IResult<&str, MessageRef<'_>> {
+    // Everything the grammar stops at is ASCII, so these slices are always on character boundaries
+    let suffix = |rest: &[u8]| &input[input.len() - rest.len()..];
+    match message_bytes(input.as_bytes()) {
+        Ok((rest, msg)) => Ok((suffix(rest), MessageRef {
+            kind: msg.kind,
+            name: msg.name,
+            id: msg.id,
+            arguments: msg
+                .arguments
+                .iter()
+                .map(|arg| {
+                    let start = arg.as_ptr() as usize - input.as_ptr() as usize;
+                    &input[start..start + arg.len()]
+                })
+                .collect(),
+        })),
+        Err(e) => Err(e.map(|e| nom::error::Error::new(suffix(e.input), e.code))),
+    }
+}
+
+/// The parser combinator for messages. One could write a grammar that utilizes this parser with nom.
+pub fn message(input: &str) -> IResult<&str, Message> {
+    map(message_ref, Message::from)(input)
+}
+
+#[cfg(test)]
+mod parser_tests {
+    use super::*;
+
+    const NOTHING: &[u8] = b"";
+
+    #[test]
+    fn test_msg_type() {
+        assert_eq!(Ok((NOTHING, MessageKind::Reply)), kind(b"!"));
+        assert_eq!(Ok((NOTHING, MessageKind::Inform)), kind(b"#"));
+        assert_eq!(Ok((NOTHING, MessageKind::Request)), kind(b"?"));
+    }
+
+    #[test]
+    fn test_name() {
+        assert_eq!(Ok((NOTHING, "set-rate")), name(b"set-rate"));
+        assert_eq!(Ok((NOTHING, "foobar")), name(b"foobar"));
+        assert_eq!(Ok((NOTHING, "f00-bar")), name(b"f00-bar"));
+    }
+
+    #[test]
+    fn test_msg_id() {
+        assert_eq!(Ok((NOTHING, MessageId::new(123).unwrap())), id(b"[123]"));
+        assert_eq!(Ok((NOTHING, MessageId::new(100).unwrap())), id(b"[100]"));
+        assert_eq!(Ok((NOTHING, MessageId::new(9).unwrap())), id(b"[9]"));
+        assert!(id(b"[0]").is_err());
+        assert!(message_id(ParseMode::Strict)(b"[0]").is_err());
+        assert_eq!(Ok((NOTHING, None)), message_id(ParseMode::Lenient)(b"[0]"));
+    }
+
+    #[test]
+    fn test_whitespace() {
+        assert_eq!(Ok((NOTHING, &b" "[..])), whitespace(b" "));
+        assert_eq!(Ok((NOTHING, &b"    "[..])), whitespace(b"    "));
+        assert_eq!(Ok((NOTHING, &b"\t    \t"[..])), whitespace(b"\t    \t"));
+    }
+
+    #[test]
+    fn test_escaped() {
+        for e in [r"\\", r"\_", r"\0", r"\n", r"\r", r"\e", r"\t", r"\@"] {
+            assert_eq!(Ok((NOTHING, e.as_bytes())), escape(e.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_eol() {
+        assert_eq!(Ok((NOTHING, &b"\n"[..])), eol(b"\n"));
+        assert_eq!(Ok((NOTHING, &b"\r"[..])), eol(b"\r"));
+    }
+
+    #[test]
+    fn test_plain() {
+        assert_eq!(Ok((NOTHING, &b"6.1"[..])), plain(b"6.1"));
+        assert_eq!(Ok((NOTHING, &b"invalid"[..])), plain(b"invalid"));
+        assert_eq!(
+            Ok((&b"\\_request."[..], &b"Unknown"[..])),
+            plain(b"Unknown\\_request.")
+        );
+    }
+
+    #[test]
+    fn test_argument() {
+        assert_eq!(Ok((NOTHING, &b"6.1"[..])), argument(b"6.1"));
+        assert_eq!(Ok((NOTHING, &b"invalid"[..])), argument(b"invalid"));
+        assert_eq!(
+            Ok((NOTHING, &b"Unknown\\_request."[..])),
+            argument(b"Unknown\\_request.")
+        );
+    }
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            Message::new(MessageKind::Request, "set-rate", None, vec!["5.1"]).unwrap(),
+            message("?set-rate 5.1").unwrap().1
+        );
         assert_eq!(
             Message::new(MessageKind::Request, "set-rate", None, vec!["5.1"]).unwrap(),
             message("?set-rate 5.1\n").unwrap().1
@@ -353,11 +1352,17 @@ mod parser_tests {
                 .1
         );
         assert_eq!(
-            Message::new(MessageKind::Request, "set-rate", Some(123), vec!["4.1"]).unwrap(),
+            Message::new(MessageKind::Request, "set-rate", MessageId::new(123), vec![
+                "4.1"
+            ])
+            .unwrap(),
             message("?set-rate[123] 4.1").unwrap().1
         );
         assert_eq!(
-            Message::new(MessageKind::Reply, "set-rate", Some(123), vec!["ok"]).unwrap(),
+            Message::new(MessageKind::Reply, "set-rate", MessageId::new(123), vec![
+                "ok"
+            ])
+            .unwrap(),
             message("!set-rate[123] ok").unwrap().1
         );
         assert_eq!(
@@ -374,7 +1379,7 @@ mod parser_tests {
             Message::new(
                 MessageKind::Request,
                 "sensor-list",
-                Some(420),
+                MessageId::new(420),
                 Vec::<String>::new()
             )
             .unwrap(),
@@ -447,11 +1452,10 @@ mod parser_tests {
 impl FromStr for Message {
     type Err = KatcpError;
 
+    /// Parses `s` as exactly one message with [`ParseMode::Strict`], so anything after the first line ending is an
+    /// error. Use [`message`] to parse a message off the front of a longer input.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match message(s) {
-            Ok((_, m)) => Ok(m),
-            Err(e) => Err(KatcpError::ParseError(own_nom_err(e))),
-        }
+        Self::parse(s, ParseMode::Strict)
     }
 }
 
@@ -463,13 +1467,244 @@ impl TryFrom<&str> for Message {
     }
 }
 
+impl<'a> TryFrom<&'a str> for MessageRef<'a> {
+    type Error = KatcpError;
+
+    fn try_from(s: &'a str) -> Result<Self, Self::Error> {
+        Self::parse(s, ParseMode::Strict)
+    }
+}
+
+#[cfg(test)]
+mod parse_error_tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    fn diagnose(s: &str) -> (Component, usize, &'static str) {
+        let e = ParseError::diagnose(s.as_bytes(), ParseMode::Strict);
+        (e.component(), e.offset(), e.expected())
+    }
+
+    #[test]
+    fn test_components() {
+        assert_eq!(
+            (Component::Kind, 0, "one of ?, ! or #"),
+            diagnose("set-rate 5.1")
+        );
+        assert_eq!((Component::Name, 1, "a letter"), diagnose("?5et-rate"));
+        assert_eq!(
+            (Component::Name, 4, "a letter, digit or -"),
+            diagnose("?set_rate 5.1")
+        );
+        assert_eq!(
+            (
+                Component::Id,
+                9,
+                "an id between 1 and 4294967295 in square brackets"
+            ),
+            diagnose("?set-rate[0] 5.1")
+        );
+        assert_eq!(
+            (Component::LineEnding, 14, "whitespace or a line ending"),
+            diagnose("?set-rate[123]5.1")
+        );
+        assert_eq!(
+            (Component::Argument(0), 13, r"an escaped null (\0)"),
+            diagnose("?set-rate 5.1\0 foo\n")
+        );
+        assert_eq!(
+            (
+                Component::Argument(2),
+                16,
+                r"a valid escape sequence (one of \\, \_, \0, \n, \r, \e, \t or \@)"
+            ),
+            diagnose(r"?set-rate 5.1 a \x")
+        );
+    }
+
+    #[test]
+    fn test_rendering() {
+        let e = ParseError::diagnose("#log\twarn caf\u{e9} b\\ad\n".as_bytes(), ParseMode::Strict);
+        assert_eq!(Component::Argument(2), e.component());
+        assert_eq!(17, e.offset());
+        assert_eq!(17, e.column());
+        assert_eq!("#log\twarn caf\u{e9} b\\ad", e.line());
+        assert_eq!(
+            "expected a valid escape sequence (one of \\\\, \\_, \\0, \\n, \\r, \\e, \\t or \\@) in argument 2 at column 17\n#log\twarn caf\u{e9} b\\ad\n    \t           ^",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_rendering() {
+        let e = ParseError::diagnose(b"#reg-dump \xff\xfe\xfd\xfc \\q", ParseMode::Strict);
+        assert_eq!(Component::Argument(1), e.component());
+        assert_eq!(23, e.offset());
+        assert_eq!(16, e.column());
+        assert_eq!("#reg-dump \u{fffd}\u{fffd}\u{fffd}\u{fffd} \\q", e.line());
+        assert_eq!(&e.line()[e.offset()..], "\\q");
+        assert!(e.to_string().ends_with("\n               ^"));
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(
+            Err(KatcpError::ParseError(ParseError {
+                line: "set rate".into(),
+                offset: 3,
+                component: Component::Name,
+                expected: "a letter, digit or -",
+            })),
+            Message::new(MessageKind::Request, "set rate", None, vec!["5.1"])
+        );
+        let e = Message::new(MessageKind::Request, "set-rate", None, vec!["5.1", ""]);
+        if let Err(KatcpError::ParseError(e)) = e {
+            assert_eq!(Component::Argument(1), e.component());
+            assert_eq!(0, e.offset());
+        } else {
+            panic!()
+        }
+        assert!(Message::new(MessageKind::Request, "set-rate", None, vec!["5 1"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_modes() {
+        let expected = Message::new(MessageKind::Request, "set-rate", None, vec!["5.1"]).unwrap();
+        for quirky in [
+            "?set-rate 5.1\r\n",
+            "\n\n?set-rate 5.1\n",
+            "  \t?set-rate 5.1",
+            "?set-rate[0] 5.1\n",
+            "?set-rate 5.1\n\r\n",
+        ] {
+            assert!(Message::parse(quirky, ParseMode::Strict).is_err());
+            assert_eq!(
+                Ok(expected.clone()),
+                Message::parse(quirky, ParseMode::Lenient)
+            );
+        }
+        assert_eq!(Ok(expected), "?set-rate 5.1\n".parse());
+        // Lenient mode is still a single message
+        let e = Message::parse("?set-rate 5.1\n?watchdog\n", ParseMode::Lenient).unwrap_err();
+        if let KatcpError::ParseError(e) = e {
+            assert_eq!(Component::LineEnding, e.component());
+            assert_eq!(13, e.offset());
+        } else {
+            panic!()
+        }
+        assert_eq!(
+            MessageId::new(123),
+            MessageRef::parse("?set-rate[123] 5.1", ParseMode::Lenient)
+                .unwrap()
+                .id()
+        );
+    }
+}
+
+#[cfg(test)]
+mod builder_tests {
+    use super::*;
+    use crate::messages::common::RetCode;
+
+    struct Unescaped;
+
+    impl ToKatcpArgument for Unescaped {
+        fn to_argument(&self) -> String {
+            "not escaped".into()
+        }
+    }
+
+    #[test]
+    fn test_builder() {
+        assert_eq!(
+            Message::new(MessageKind::Request, "set-rate", MessageId::new(5), vec![
+                "4.1",
+                r"some\_text",
+                r"\@",
+                "1"
+            ]),
+            Message::request("set-rate")
+                .id(MessageId::new(5).unwrap())
+                .arg(&4.1f32)
+                .arg("some text")
+                .arg("")
+                .arg(&true)
+                .build()
+        );
+        assert_eq!(
+            Message::new(MessageKind::Inform, "sensor-value", None, vec![
+                "1", "2", "3"
+            ]),
+            Message::inform("sensor-value").args(&[1u32, 2, 3]).build()
+        );
+        assert_eq!(
+            Message::new(MessageKind::Reply, "watchdog", None, vec!["ok"]),
+            Message::reply("watchdog").arg(&RetCode::Ok).build()
+        );
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let e = Message::request("set rate").arg(&1u32).build().unwrap_err();
+        assert!(matches!(e, KatcpError::ParseError(e) if e.component() == Component::Name));
+        let e = Message::request("set-rate")
+            .arg(&1u32)
+            .arg(&Unescaped)
+            .build()
+            .unwrap_err();
+        assert!(matches!(e, KatcpError::ParseError(e) if e.component() == Component::Argument(1)));
+        // A `Message` can't hold bytes that aren't UTF-8
+        let e = Message::inform("reg-dump")
+            .arg(&1u32)
+            .arg(&b"\xff\xfe"[..])
+            .build()
+            .unwrap_err();
+        assert_eq!(Some(1), e.context().and_then(|context| context.index));
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use super::*;
+
+    #[test]
+    fn test_message_serde() {
+        let msg = Message::request("set-rate")
+            .id(MessageId::new(5).unwrap())
+            .arg(&4.1f32)
+            .arg("some text")
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            r#"{"kind":"request","name":"set-rate","id":5,"arguments":["4.1","some\\_text"]}"#,
+            json
+        );
+        assert_eq!(msg, serde_json::from_str(&json).unwrap());
+        // Deserialization validates just like `Message::new`
+        assert!(serde_json::from_str::<Message>(
+            r#"{"kind":"inform","name":"set rate","id":null,"arguments":[]}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<Message>(
+            r#"{"kind":"inform","name":"set-rate","id":null,"arguments":["a b"]}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<Message>(
+            r#"{"kind":"inform","name":"set-rate","id":0,"arguments":[]}"#
+        )
+        .is_err());
+    }
+}
+
 #[cfg(test)]
 mod deserialization_tests {
     use super::*;
 
     #[test]
     fn deserialization() {
-        let msg = Message::new(MessageKind::Inform, "foo-bar", Some(123), vec![
+        let msg = Message::new(MessageKind::Inform, "foo-bar", MessageId::new(123), vec![
             "foo", "bar",
         ])
         .unwrap();
@@ -480,51 +1715,784 @@ mod deserialization_tests {
         // TryInto
         assert_eq!(msg, msg_str.try_into().unwrap());
     }
+
+    #[test]
+    fn borrowed_deserialization() {
+        let msg_str = r"#foo-bar[123] foo\_bar baz";
+        let msg_ref: MessageRef = msg_str.try_into().unwrap();
+        assert_eq!(MessageKind::Inform, msg_ref.kind());
+        assert_eq!("foo-bar", msg_ref.name());
+        assert_eq!(MessageId::new(123), msg_ref.id());
+        assert_eq!(&[r"foo\_bar", "baz"], msg_ref.arguments());
+        let msg: Message = msg_ref.clone().into();
+        assert_eq!(Message::from_str(msg_str).unwrap(), msg);
+        assert_eq!(msg_ref, MessageRef::from(&msg));
+        assert!(MessageRef::try_from("foo-bar").is_err());
+    }
+}
+
+// Byte-level parsing
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// A borrowed message whose arguments are raw bytes, for peers that send arguments that aren't valid UTF-8
+///
+/// The spec only gives special meaning to the escape set, so arguments may otherwise carry arbitrary bytes (binary
+/// blobs, Latin-1 text, etc.). The name of a message is always ASCII, so it is still a `&str`.
+///
+/// ```
+/// use katcp::{
+///     messages::common::FromKatcpArgument,
+///     protocol::{MessageBytes, MessageKind},
+/// };
+///
+/// let msg: MessageBytes = b"#reg-dump \xde\xad\\0\xbe\xef"
+///     .as_slice()
+///     .try_into()
+///     .unwrap();
+/// assert_eq!(msg.kind(), MessageKind::Inform);
+/// let dump = Vec::<u8>::from_argument_bytes(msg.arguments()[0]).unwrap();
+/// assert_eq!(dump, b"\xde\xad\0\xbe\xef");
+/// ```
+pub struct MessageBytes<'a> {
+    /// The message kind
+    pub(crate) kind: MessageKind,
+    /// The message name
+    pub(crate) name: &'a str,
+    /// The optional, positive message id
+    pub(crate) id: Option<MessageId>,
+    /// The (potentially empty) vector of raw, escaped message arguments
+    pub(crate) arguments: Vec<&'a [u8]>,
+}
+
+impl<'a> MessageBytes<'a> {
+    /// A constructor that validates the `name` and the (already escaped) `arguments`
+    pub fn new(
+        kind: MessageKind,
+        name: &'a str,
+        id: Option<MessageId>,
+        arguments: Vec<&'a [u8]>,
+    ) -> Result<Self, KatcpError> {
+        ParseError::validate_name(name)?;
+        for (index, argument) in arguments.iter().enumerate() {
+            ParseError::validate_argument(index, argument)?;
+        }
+        Ok(Self {
+            kind,
+            name,
+            id,
+            arguments,
+        })
+    }
+
+    /// Kind getter
+    pub fn kind(&self) -> MessageKind {
+        self.kind
+    }
+
+    /// Name getter
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Id getter
+    pub fn id(&self) -> Option<MessageId> {
+        self.id
+    }
+
+    /// Arguments getter
+    pub fn arguments(&self) -> &[&'a [u8]] {
+        &self.arguments
+    }
+
+    /// Parses `input` as a single message (with an optional line ending), following the rules of `mode`
+    pub fn parse(input: &'a [u8], mode: ParseMode) -> Result<Self, KatcpError> {
+        let line = match mode {
+            ParseMode::Strict => input,
+            ParseMode::Lenient => trim_blank(input),
+        };
+        let parsed = message_in(mode)(line);
+        let (rest, msg) = parsed.map_err(|_| ParseError::diagnose(line, mode))?;
+        let rest = match mode {
+            ParseMode::Strict => rest,
+            ParseMode::Lenient => trim_blank(rest),
+        };
+        if rest.is_empty() {
+            Ok(msg)
+        } else {
+            Err(ParseError::new(
+                line,
+                line.len() - rest.len(),
+                Component::LineEnding,
+                "the end of the input after a single line ending",
+            )
+            .into())
+        }
+    }
+
+    /// Serializes the message into bytes, including the trailing newline
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        serialize(
+            self.kind,
+            self.name,
+            self.id,
+            &self.arguments,
+            &mut |piece| {
+                bytes.extend_from_slice(piece);
+                Ok::<_, Infallible>(())
+            },
+        )
+        .unwrap_or_else(|e| match e {});
+        bytes
+    }
+
+    /// Serializes the message into `writer`, including the trailing newline
+    #[cfg(feature = "std")]
+    pub fn write_to<W: io::Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        write_message(writer, self.kind, self.name, self.id, &self.arguments)
+    }
+}
+
+impl<'a> From<MessageRef<'a>> for MessageBytes<'a> {
+    fn from(msg: MessageRef<'a>) -> Self {
+        Self {
+            kind: msg.kind,
+            name: msg.name,
+            id: msg.id,
+            arguments: msg.arguments.iter().map(|s| s.as_bytes()).collect(),
+        }
+    }
+}
+
+impl<'a> TryFrom<MessageBytes<'a>> for MessageRef<'a> {
+    type Error = KatcpError;
+
+    /// Fails if any of the arguments are not valid UTF-8
+    fn try_from(msg: MessageBytes<'a>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            kind: msg.kind,
+            name: msg.name,
+            id: msg.id,
+            arguments: msg
+                .arguments
+                .iter()
+                .enumerate()
+                .map(|(index, b)| {
+                    core::str::from_utf8(b).map_err(|_| {
+                        KatcpError::bad_argument(String::from_utf8_lossy(b)).at_index(index)
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for MessageBytes<'a> {
+    type Error = KatcpError;
+
+    fn try_from(b: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::parse(b, ParseMode::Strict)
+    }
+}
+
+#[cfg(test)]
+mod bytes_tests {
+    use alloc::string::ToString;
+
+    use katcp_derive::KatcpMessage;
+
+    use super::*;
+    use crate::prelude::*;
+
+    #[derive(KatcpMessage, Debug, PartialEq, Eq, Clone)]
+    enum RegDump {
+        Inform { address: u32, data: Vec<u8> },
+    }
+
+    #[test]
+    fn test_argument_bytes() {
+        assert_eq!(Ok((&b""[..], &b"6.1"[..])), argument(b"6.1"));
+        assert_eq!(
+            Ok((&b" foo"[..], &b"\xde\xad\\_\xbe\xef"[..])),
+            argument(b"\xde\xad\\_\xbe\xef foo")
+        );
+        assert!(argument(b"\0").is_err());
+    }
+
+    #[test]
+    fn test_message_bytes() {
+        let msg = message_bytes(b"#reg-dump[7] \xde\xad\xbe\xef caf\xe9\n")
+            .unwrap()
+            .1;
+        assert_eq!(
+            MessageBytes::new(MessageKind::Inform, "reg-dump", MessageId::new(7), vec![
+                b"\xde\xad\xbe\xef",
+                b"caf\xe9"
+            ])
+            .unwrap(),
+            msg
+        );
+        assert_eq!(
+            b"#reg-dump[7] \xde\xad\xbe\xef caf\xe9\n".to_vec(),
+            msg.to_bytes()
+        );
+        // Not UTF-8, so we can't make a `MessageRef`
+        assert!(MessageRef::try_from(msg).is_err());
+    }
+
+    #[test]
+    fn test_utf8_message_bytes() {
+        let msg_str = r"!set-rate[123] fail Hardware\_did\_not\_respond.";
+        let msg = MessageBytes::try_from(msg_str.as_bytes()).unwrap();
+        let msg_ref = MessageRef::try_from(msg.clone()).unwrap();
+        assert_eq!(MessageRef::try_from(msg_str).unwrap(), msg_ref);
+        assert_eq!(msg, MessageBytes::from(msg_ref));
+    }
+
+    #[test]
+    fn test_bad_message_bytes() {
+        assert!(MessageBytes::try_from(&b"#reg-dump \xde\0"[..]).is_err());
+        assert!(MessageBytes::new(MessageKind::Inform, "reg-dump", None, vec![b"a b"]).is_err());
+    }
+
+    #[test]
+    fn test_typed_message_bytes() {
+        let line = b"#reg-dump 16 \xde\xad\\_\xbe\xef\n";
+        let dump = RegDump::try_from(message_bytes(line).unwrap().1).unwrap();
+        assert_eq!(
+            RegDump::Inform {
+                address: 16,
+                data: b"\xde\xad \xbe\xef".to_vec()
+            },
+            dump
+        );
+        #[cfg(feature = "std")]
+        {
+            let mut written = vec![];
+            dump.write_to(None, &mut written).unwrap();
+            assert_eq!(line.to_vec(), written);
+        }
+        // A `Message` can't hold it, so that's an error rather than a lossy conversion
+        let e = dump.to_message(None).unwrap_err();
+        assert_eq!(Some(1), e.context().and_then(|context| context.index));
+        // Text still works either way
+        let text = RegDump::Inform {
+            address: 16,
+            data: b"plain text".to_vec(),
+        };
+        let msg = text.to_message(None).unwrap();
+        assert_eq!(r"#reg-dump 16 plain\_text", msg.to_string().trim_end());
+        assert_eq!(text, RegDump::try_from(msg).unwrap());
+    }
+}
+
+/// A stateful decoder for pulling [`Message`]s out of a stream of bytes
+///
+/// Bytes from a socket rarely line up with message boundaries, so the decoder buffers any partial line it is given
+/// and only yields a message once a line ending (`\n` or `\r`) has been seen. Unlike [`message`], the end of the input
+/// is *not* treated as a line ending.
+///
+/// By default lines are parsed with [`ParseMode::Strict`], so an empty line is an error, including the one between the
+/// `\r` and `\n` of a `\r\n`. A decoder made with [`MessageDecoder::with_mode`] and [`ParseMode::Lenient`] instead
+/// treats a `\r\n` pair as a single line ending and silently skips blank lines.
+///
+/// So that a peer that never sends a line ending can't make the decoder buffer forever, lines longer than
+/// [`MessageDecoder::max_line_length`] are discarded with a [`KatcpError::LineTooLong`].
+///
+/// ```
+/// use katcp::protocol::{Message, MessageDecoder};
+///
+/// let mut decoder = MessageDecoder::new();
+/// // Half of a message arrives
+/// let (consumed, msg) = decoder.decode(b"?set-rate[1");
+/// assert_eq!(consumed, 11);
+/// assert!(msg.is_none());
+/// // And then the rest, with the start of the next one
+/// let (consumed, msg) = decoder.decode(b"23] 4.1\n?watch");
+/// assert_eq!(consumed, 8);
+/// assert_eq!(
+///     msg.unwrap().unwrap(),
+///     "?set-rate[123] 4.1".parse::<Message>().unwrap()
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct MessageDecoder {
+    /// The partial line we've seen so far
+    buffer: Vec<u8>,
+    /// How to parse each completed line
+    mode: ParseMode,
+    /// The longest line (without its line ending) we'll buffer
+    max_line_length: usize,
+    /// We're lenient and the last line ended with a `\r`, so a `\n` straight after it is part of the same line ending
+    after_cr: bool,
+    /// We're skipping the rest of a line that was too long
+    discarding: bool,
+    /// The buffer holds a line that's already been decoded
+    complete: bool,
+}
+
+impl Default for MessageDecoder {
+    fn default() -> Self {
+        Self::with_mode(ParseMode::default())
+    }
+}
+
+impl MessageDecoder {
+    /// The default for [`MessageDecoder::max_line_length`], 1 MiB
+    pub const DEFAULT_MAX_LINE_LENGTH: usize = 1 << 20;
+
+    /// Creates a new strict decoder with an empty buffer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new decoder with an empty buffer that parses lines according to `mode`
+    pub fn with_mode(mode: ParseMode) -> Self {
+        Self {
+            buffer: vec![],
+            mode,
+            max_line_length: Self::DEFAULT_MAX_LINE_LENGTH,
+            after_cr: false,
+            discarding: false,
+            complete: false,
+        }
+    }
+
+    /// Sets the longest line (in bytes, without its line ending) the decoder will accept
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// The longest line (in bytes, without its line ending) the decoder will accept
+    pub fn max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+
+    /// Consumes bytes from `input` up to and including the first line ending, returning the number of bytes consumed
+    /// and the decoded message (if a line was completed).
+    ///
+    /// If there is no line ending in `input`, the whole slice is buffered and `None` is returned. The caller should
+    /// call this again with the unconsumed remainder of `input` to get the following messages. In lenient mode,
+    /// blank lines are consumed without yielding anything, as is the `\n` of a `\r\n`.
+    ///
+    /// Once a line grows past [`MessageDecoder::max_line_length`] a [`KatcpError::LineTooLong`] is returned and the
+    /// rest of that line is skipped.
+    pub fn decode(&mut self, input: &[u8]) -> (usize, Option<MessageResult>) {
+        let (consumed, msg) = self.decode_bytes(input);
+        let msg = msg.map(|msg| msg.and_then(|msg| Ok(MessageRef::try_from(msg)?.into())));
+        (consumed, msg)
+    }
+
+    /// The same as [`MessageDecoder::decode`], but yields [`MessageBytes`] that borrow from `input` (or the decoder's
+    /// buffer), so lines don't have to be UTF-8 and nothing is copied out of them.
+    ///
+    /// ```
+    /// use katcp::{prelude::*, protocol::MessageDecoder};
+    ///
+    /// let mut decoder = MessageDecoder::new();
+    /// let (_, msg) = decoder.decode_bytes(b"#reg-dump \xde\xad\xbe\xef\n");
+    /// let msg = msg.unwrap().unwrap();
+    /// let dump = Vec::<u8>::from_argument_bytes(msg.arguments()[0]).unwrap();
+    /// assert_eq!(vec![0xde, 0xad, 0xbe, 0xef], dump);
+    /// ```
+    pub fn decode_bytes<'a>(
+        &'a mut self,
+        input: &'a [u8],
+    ) -> (usize, Option<Result<MessageBytes<'a>, KatcpError>>) {
+        // The last line we handed out may have borrowed the buffer, so we can only throw it away now
+        if core::mem::take(&mut self.complete) {
+            self.buffer.clear();
+        }
+        if input.is_empty() {
+            return (0, None);
+        }
+        if core::mem::take(&mut self.after_cr) && input[0] == b'\n' {
+            return (1, None);
+        }
+        let end = match input.iter().position(|b| *b == b'\n' || *b == b'\r') {
+            Some(end) => end,
+            None => {
+                if !self.discarding {
+                    if self.buffer.len() + input.len() > self.max_line_length {
+                        return (input.len(), Some(Err(self.too_long())));
+                    }
+                    self.buffer.extend_from_slice(input);
+                }
+                return (input.len(), None);
+            }
+        };
+        self.after_cr = self.mode == ParseMode::Lenient && input[end] == b'\r';
+        if core::mem::take(&mut self.discarding) {
+            return (end + 1, None);
+        }
+        if self.buffer.len() + end > self.max_line_length {
+            let err = self.too_long();
+            self.discarding = false;
+            return (end + 1, Some(Err(err)));
+        }
+        let mode = self.mode;
+        let line = if self.buffer.is_empty() {
+            // Fast path, the whole line is in `input` so we don't need to copy
+            &input[..end]
+        } else {
+            self.buffer.extend_from_slice(&input[..end]);
+            self.complete = true;
+            &self.buffer
+        };
+        if mode == ParseMode::Lenient && trim_blank(line).is_empty() {
+            return (end + 1, None);
+        }
+        (end + 1, Some(MessageBytes::parse(line, mode)))
+    }
+
+    /// Consumes all of `input`, returning every message that was completed along the way
+    pub fn decode_all(&mut self, mut input: &[u8]) -> Vec<MessageResult> {
+        let mut msgs = vec![];
+        while !input.is_empty() {
+            let (consumed, msg) = self.decode(input);
+            msgs.extend(msg);
+            input = &input[consumed..];
+        }
+        msgs
+    }
+
+    /// The number of bytes of an incomplete line currently held by the decoder
+    pub fn buffered(&self) -> usize {
+        if self.complete {
+            0
+        } else {
+            self.buffer.len()
+        }
+    }
+
+    /// Discards any partially received line, returning the decoder to the state it was made in
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.after_cr = false;
+        self.complete = false;
+        self.discarding = false;
+    }
+
+    /// Drops the line we've buffered so far and skips the rest of it
+    fn too_long(&mut self) -> KatcpError {
+        self.buffer.clear();
+        self.discarding = true;
+        KatcpError::LineTooLong(self.max_line_length)
+    }
+}
+
+#[cfg(test)]
+mod decoder_tests {
+    use super::*;
+
+    #[test]
+    fn test_whole_lines() {
+        let mut decoder = MessageDecoder::new();
+        let msgs = decoder.decode_all(b"?set-rate 5.1\n!set-rate ok\r#foo-bar[123] foo bar\n");
+        assert_eq!(
+            vec![
+                Message::new(MessageKind::Request, "set-rate", None, vec!["5.1"]),
+                Message::new(MessageKind::Reply, "set-rate", None, vec!["ok"]),
+                Message::new(MessageKind::Inform, "foo-bar", MessageId::new(123), vec![
+                    "foo", "bar"
+                ]),
+            ],
+            msgs
+        );
+        assert_eq!(0, decoder.buffered());
+    }
+
+    #[test]
+    fn test_partial_lines() {
+        let mut decoder = MessageDecoder::new();
+        let input = b"#sensor-list drive.dc-voltage-elev Drive\\_bus\\_voltage V float 0.0 900.0\n";
+        // Feed the message one byte at a time
+        for (i, byte) in input[..input.len() - 1].iter().enumerate() {
+            assert_eq!((1, None), decoder.decode(&[*byte]));
+            assert_eq!(i + 1, decoder.buffered());
+        }
+        let (consumed, msg) = decoder.decode(b"\n?watchdog");
+        assert_eq!(1, consumed);
+        assert_eq!(
+            Message::new(MessageKind::Inform, "sensor-list", None, vec![
+                "drive.dc-voltage-elev",
+                r"Drive\_bus\_voltage",
+                "V",
+                "float",
+                "0.0",
+                "900.0"
+            ])
+            .unwrap(),
+            msg.unwrap().unwrap()
+        );
+        assert_eq!(0, decoder.buffered());
+    }
+
+    #[test]
+    fn test_truncated_line() {
+        let mut decoder = MessageDecoder::new();
+        // Without a line ending, this must not be treated as a complete message
+        assert!(decoder.decode_all(b"?set-rate 5.1").is_empty());
+        assert_eq!(13, decoder.buffered());
+        assert_eq!(
+            vec![Message::new(MessageKind::Request, "set-rate", None, vec![
+                "5.12"
+            ])],
+            decoder.decode_all(b"2\n")
+        );
+    }
+
+    #[test]
+    fn test_bad_lines() {
+        let mut decoder = MessageDecoder::new();
+        let msgs = decoder.decode_all(b"set-rate 5.1\n?set-\xffrate\n?watchdog\n");
+        assert_eq!(3, msgs.len());
+        assert!(msgs[0].is_err());
+        assert!(msgs[1].is_err());
+        assert!(msgs[2].is_ok());
+        decoder.decode(b"?half-a-mess");
+        decoder.clear();
+        assert_eq!(0, decoder.buffered());
+    }
+
+    #[test]
+    fn test_binary_lines() {
+        let mut decoder = MessageDecoder::new();
+        assert_eq!((12, None), decoder.decode_bytes(b"#reg-dump \xde\xad"));
+        let (consumed, msg) = decoder.decode_bytes(b"\xbe\xef\n?watchdog\n");
+        assert_eq!(3, consumed);
+        assert_eq!(
+            MessageBytes::new(MessageKind::Inform, "reg-dump", None, vec![
+                b"\xde\xad\xbe\xef"
+            ]),
+            msg.unwrap()
+        );
+        let (consumed, msg) = decoder.decode_bytes(b"?watchdog\n");
+        assert_eq!(10, consumed);
+        assert_eq!(Some("watchdog"), msg.unwrap().ok().map(|m| m.name()));
+        assert_eq!(0, decoder.buffered());
+        // The owned decoder can't hold on to arguments that aren't UTF-8
+        let msgs = MessageDecoder::new().decode_all(b"#reg-dump \xff\n");
+        assert!(matches!(msgs[0], Err(KatcpError::BadArgument { .. })));
+    }
+
+    #[test]
+    fn test_crlf() {
+        let lenient = || MessageDecoder::with_mode(ParseMode::Lenient);
+        let msgs = lenient().decode_all(b"?watchdog\r\n?halt\r\n");
+        assert_eq!(
+            vec![
+                Message::new(MessageKind::Request, "watchdog", None, Vec::<&str>::new()),
+                Message::new(MessageKind::Request, "halt", None, Vec::<&str>::new()),
+            ],
+            msgs
+        );
+        // Even when the pair is split across reads
+        let mut decoder = lenient();
+        assert!(decoder.decode(b"?watchdog\r").1.unwrap().is_ok());
+        assert_eq!((1, None), decoder.decode(b"\n?halt\n"));
+        assert!(decoder.decode(b"?halt\n").1.unwrap().is_ok());
+        // But a `\n\r` is two line endings
+        assert_eq!(
+            3,
+            MessageDecoder::new().decode_all(b"?halt\n\r?halt\n").len()
+        );
+        // Strictly, like `Message::parse`, there's no such thing as a `\r\n`
+        let msgs = MessageDecoder::new().decode_all(b"?watchdog\r\n");
+        assert_eq!(2, msgs.len());
+        assert!(msgs[0].is_ok());
+        assert!(msgs[1].is_err());
+    }
+
+    #[test]
+    fn test_max_line_length() {
+        let mut decoder = MessageDecoder::new().with_max_line_length(8);
+        assert_eq!((6, None), decoder.decode(b"?watch"));
+        // The line goes over the limit before it's finished, so we bail out straight away
+        assert_eq!(
+            (6, Some(Err(KatcpError::LineTooLong(8)))),
+            decoder.decode(b"dog-fo")
+        );
+        assert_eq!(0, decoder.buffered());
+        // And skip the rest of it
+        assert_eq!((3, None), decoder.decode(b"od\n"));
+        assert_eq!(
+            vec![
+                Err(KatcpError::LineTooLong(8)),
+                Message::new(MessageKind::Request, "halt", None, Vec::<&str>::new()),
+            ],
+            decoder.decode_all(b"?watchdog-food\n?halt\n")
+        );
+    }
+
+    #[test]
+    fn test_lenient_lines() {
+        let input = b"?watchdog\r\n\n  \n  !watchdog[0] ok\r\n";
+        // Strictly, the `\n` of each `\r\n` ends another (empty) line
+        let msgs = MessageDecoder::new().decode_all(input);
+        assert_eq!(6, msgs.len());
+        assert_eq!(5, msgs.iter().filter(|m| m.is_err()).count());
+        assert!(msgs[0].is_ok());
+        assert!(msgs[1].is_err());
+
+        let msgs = MessageDecoder::with_mode(ParseMode::Lenient).decode_all(input);
+        assert_eq!(
+            vec![
+                Message::new(MessageKind::Request, "watchdog", None, Vec::<&str>::new()),
+                Message::new(MessageKind::Reply, "watchdog", None, vec!["ok"]),
+            ],
+            msgs
+        );
+    }
 }
 
 // Serialization
 impl Display for Message {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        let type_char = match self.kind {
-            MessageKind::Request => '?',
-            MessageKind::Reply => '!',
-            MessageKind::Inform => '#',
-        };
-        let id_str = match self.id {
-            Some(id) => format!("[{}]", id),
-            None => "".to_owned(),
-        };
-        let mut args_str = "".to_owned();
-        self.arguments.iter().for_each(|argument| {
-            args_str.push(' ');
-            args_str.push_str(argument);
-        });
-        writeln!(f, "{}{}{}{}", type_char, self.name, id_str, args_str)
+        serialize(
+            self.kind,
+            &self.name,
+            self.id,
+            &self.arguments,
+            &mut |piece| {
+                // Every piece is either ASCII or a whole argument, so this never fails
+                f.write_str(core::str::from_utf8(piece).map_err(|_| core::fmt::Error)?)
+            },
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl Message {
+    /// Serializes the message into `writer`, including the trailing newline. The output is identical to `Display`,
+    /// but goes straight to the writer without any intermediate allocations.
+    ///
+    /// ```
+    /// use katcp::protocol::{Message, MessageId, MessageKind};
+    ///
+    /// let msg = Message::new(MessageKind::Inform, "foo-bar", MessageId::new(2), vec![
+    ///     "baz",
+    /// ])
+    /// .unwrap();
+    /// let mut buf = vec![];
+    /// msg.write_to(&mut buf).unwrap();
+    /// assert_eq!(b"#foo-bar[2] baz\n", buf.as_slice());
+    /// ```
+    pub fn write_to<W: io::Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        write_message(writer, self.kind, &self.name, self.id, &self.arguments)
+    }
+}
+
+/// Writes a message made up of the given parts into `writer`, including the trailing newline
+///
+/// Like `Display` for [`Message`], this assumes the `name` and `arguments` are valid (and already escaped). This is
+/// what serializes messages without needing to build a [`Message`] first.
+#[cfg(feature = "std")]
+pub fn write_message<W: io::Write + ?Sized, A: AsRef<[u8]>>(
+    writer: &mut W,
+    kind: MessageKind,
+    name: &str,
+    id: Option<MessageId>,
+    arguments: &[A],
+) -> io::Result<()> {
+    serialize(kind, name, id, arguments, &mut |piece| {
+        writer.write_all(piece)
+    })
+}
+
+/// Serializes a message made up of the given parts into `put` a piece at a time, including the trailing newline
+///
+/// This assumes the `name` and `arguments` are valid (and already escaped). Everything that writes messages goes
+/// through here, so the different outputs can't disagree.
+fn serialize<A: AsRef<[u8]>, E>(
+    kind: MessageKind,
+    name: &str,
+    id: Option<MessageId>,
+    arguments: impl IntoIterator<Item = A>,
+    put: &mut impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    serialize_header(kind, name, id, put)?;
+    for argument in arguments {
+        put(b" ")?;
+        put(argument.as_ref())?;
+    }
+    put(b"\n")
+}
+
+/// Serializes the kind, name and id that start a message into `put`, for when the arguments come from elsewhere
+pub(crate) fn serialize_header<E>(
+    kind: MessageKind,
+    name: &str,
+    id: Option<MessageId>,
+    put: &mut impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    put(&[kind.symbol() as u8])?;
+    put(name.as_bytes())?;
+    if let Some(id) = id {
+        // Room for u32::MAX, so we don't need to allocate
+        let mut digits = [0u8; 10];
+        let mut start = digits.len();
+        let mut id = id.get();
+        while id > 0 {
+            start -= 1;
+            digits[start] = b'0' + (id % 10) as u8;
+            id /= 10;
+        }
+        put(b"[")?;
+        put(&digits[start..])?;
+        put(b"]")?;
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod serialization_tests {
+    use alloc::string::ToString;
+
     use super::*;
 
     #[test]
     fn serialization() {
-        let msg = Message::new(MessageKind::Inform, "foo-bar", Some(123), vec![
+        let msg = Message::new(MessageKind::Inform, "foo-bar", MessageId::new(123), vec![
             "foo", "bar",
         ])
         .unwrap();
         let msg_str = "#foo-bar[123] foo bar\n";
         assert_eq!(msg_str, msg.to_string());
     }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn write_to_matches_display() {
+        let msgs = [
+            Message::new(MessageKind::Inform, "foo-bar", MessageId::new(123), vec![
+                "foo", "bar",
+            ]),
+            Message::new(MessageKind::Request, "watchdog", None, Vec::<&str>::new()),
+            Message::new(MessageKind::Reply, "log", MessageId::new(4294967295), vec![
+                "caf\\_\u{e9}",
+            ]),
+        ];
+        for msg in msgs {
+            let msg = msg.unwrap();
+            let mut buf = vec![];
+            msg.write_to(&mut buf).unwrap();
+            assert_eq!(msg.to_string().as_bytes(), buf.as_slice());
+            assert_eq!(MessageBytes::from(MessageRef::from(&msg)).to_bytes(), buf);
+        }
+    }
 }
 
 #[cfg(test)]
 mod there_and_back_tests {
+    use alloc::string::ToString;
+
     use super::*;
 
     #[test]
     fn struct_and_back() {
-        let msg = Message::new(MessageKind::Inform, "foo-bar", Some(123), vec![
+        let msg = Message::new(MessageKind::Inform, "foo-bar", MessageId::new(123), vec![
             "foo", "bar",
         ])
         .unwrap();
@@ -537,3 +2505,24 @@ mod there_and_back_tests {
         assert_eq!(Message::from_str(msg_str).unwrap().to_string(), msg_str);
     }
 }
+
+#[cfg(all(test, feature = "arbitrary"))]
+mod arbitrary_tests {
+    use arbitrary::{Arbitrary, Unstructured};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn generated_messages_roundtrip(bytes in proptest::collection::vec(any::<u8>(), 0..512)) {
+            if let Ok(message) = Message::arbitrary(&mut Unstructured::new(&bytes)) {
+                let Message { kind, name, id, arguments } = message.clone();
+                let validated = Message::new(kind, name, id, arguments);
+                prop_assert_eq!(validated.as_ref(), Ok(&message));
+                let raw = message.to_string();
+                prop_assert_eq!(Message::try_from(raw.as_str()), Ok(message));
+            }
+        }
+    }
+}
@@ -1,39 +1,148 @@
-/// Unescapes a string revieved from katcp using the eight valid escape characters
-pub(crate) fn unescape(input: &str) -> String {
-    input
-        .replace(r"\_", " ")
-        .replace(r"\0", "\0")
-        .replace(r"\n", "\n")
-        .replace(r"\r", "\r")
-        .replace(r"\e", "\u{001B}")
-        .replace(r"\t", "\t")
-        .replace(r"\@", "")
-        .replace(r"\\", r"\")
+use alloc::{borrow::ToOwned, string::String, vec::Vec};
+
+use crate::protocol::KatcpError;
+
+/// The escape sequence for a byte, if it needs one to be sent over katcp
+fn escape_sequence(b: u8) -> Option<&'static str> {
+    match b {
+        b'\\' => Some(r"\\"),
+        b' ' => Some(r"\_"),
+        b'\0' => Some(r"\0"),
+        b'\n' => Some(r"\n"),
+        b'\r' => Some(r"\r"),
+        0x1B => Some(r"\e"),
+        b'\t' => Some(r"\t"),
+        _ => None,
+    }
+}
+
+/// Unescapes a string revieved from katcp using the eight valid escape characters, erroring on any other escape
+pub(crate) fn unescape(input: &str) -> Result<String, KatcpError> {
+    // Every escape sequence is ASCII, so unescaping valid UTF-8 will always give valid UTF-8
+    String::from_utf8(unescape_bytes(input.as_bytes())?)
+        .map_err(|_| KatcpError::bad_argument(input))
 }
 
 /// Escapes a string into a string suitable for katcp using the eight valid escape characters
 pub(crate) fn escape(input: &str) -> String {
     if input.is_empty() {
-        r"\@".to_owned()
-    } else {
-        input
-            .replace('\\', r"\\")
-            .replace(' ', r"\_")
-            .replace('\0', r"\0")
-            .replace('\n', r"\n")
-            .replace('\r', r"\r")
-            .replace('\u{001B}', r"\e")
-            .replace('\t', r"\t")
+        return r"\@".to_owned();
+    }
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match Some(c)
+            .filter(char::is_ascii)
+            .and_then(|c| escape_sequence(c as u8))
+        {
+            Some(seq) => output.push_str(seq),
+            None => output.push(c),
+        }
     }
+    output
+}
+
+/// Unescapes raw bytes recieved from katcp using the eight valid escape characters, erroring on any other escape
+pub(crate) fn unescape_bytes(input: &[u8]) -> Result<Vec<u8>, KatcpError> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut bytes = input.iter();
+    while let Some(&b) = bytes.next() {
+        if b != b'\\' {
+            output.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'\\') => output.push(b'\\'),
+            Some(b'_') => output.push(b' '),
+            Some(b'0') => output.push(b'\0'),
+            Some(b'n') => output.push(b'\n'),
+            Some(b'r') => output.push(b'\r'),
+            Some(b'e') => output.push(0x1B),
+            Some(b't') => output.push(b'\t'),
+            Some(b'@') => (),
+            _ => return Err(KatcpError::bad_argument(String::from_utf8_lossy(input))),
+        }
+    }
+    Ok(output)
+}
+
+/// Escapes raw bytes into bytes suitable for katcp using the eight valid escape characters
+pub(crate) fn escape_bytes(input: &[u8]) -> Vec<u8> {
+    if input.is_empty() {
+        return br"\@".to_vec();
+    }
+    let mut output = Vec::with_capacity(input.len());
+    for &b in input {
+        match escape_sequence(b) {
+            Some(seq) => output.extend_from_slice(seq.as_bytes()),
+            None => output.push(b),
+        }
+    }
+    output
 }
 
 #[cfg(test)]
 mod strings {
+    use alloc::vec;
+
+    use proptest::prelude::*;
+
     use super::*;
+    use crate::protocol::{Message, MessageKind};
 
     #[test]
     fn test_escape() {
         assert_eq!(r"This\_is\_my\_foo\n", escape("This is my foo\n"));
-        assert_eq!("This is my foo\n", unescape(r"This\_is\_my\_foo\n"));
+        assert_eq!(
+            "This is my foo\n",
+            unescape(r"This\_is\_my\_foo\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unambiguous() {
+        // An escaped backslash followed by an underscore, not a backslash and a space
+        assert_eq!(r"\_", unescape(r"\\_").unwrap());
+        assert_eq!(r"\\_", escape(r"\_"));
+        assert_eq!("\\\n", unescape(r"\\\n").unwrap());
+        assert_eq!("", unescape(r"\@").unwrap());
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        assert!(unescape(r"\x").is_err());
+        assert!(unescape(r"foo\").is_err());
+        assert!(unescape(r"\\\").is_err());
+        assert!(unescape_bytes(br"\Q").is_err());
+    }
+
+    #[test]
+    fn test_escape_bytes() {
+        let raw = b"\xde\xad \\\0\xbe\xef\n";
+        assert_eq!(b"\xde\xad\\_\\\\\\0\xbe\xef\\n".to_vec(), escape_bytes(raw));
+        assert_eq!(raw.to_vec(), unescape_bytes(&escape_bytes(raw)).unwrap());
+        assert_eq!(br"\@".to_vec(), escape_bytes(b""));
+        assert!(unescape_bytes(br"\@").unwrap().is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip(s in any::<String>()) {
+            prop_assert_eq!(unescape(&escape(&s)).unwrap(), s);
+        }
+
+        #[test]
+        fn roundtrip_escape_heavy(s in "[\\\\ _@0nret\t\n\r\x1b\x00]*") {
+            prop_assert_eq!(unescape(&escape(&s)).unwrap(), s);
+        }
+
+        #[test]
+        fn roundtrip_bytes(b in any::<Vec<u8>>()) {
+            prop_assert_eq!(unescape_bytes(&escape_bytes(&b)).unwrap(), b);
+        }
+
+        #[test]
+        fn escaped_is_valid_argument(s in any::<String>()) {
+            prop_assert!(Message::new(MessageKind::Inform, "foo", None, vec![escape(&s)]).is_ok());
+        }
     }
 }
@@ -0,0 +1,7 @@
+//! Misuse of the derive macros should be a readable compile error pointing at the offending code
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,9 @@
+use katcp_derive::KatcpArguments;
+
+#[derive(KatcpArguments)]
+union Gain {
+    linear: f32,
+    decibels: f32,
+}
+
+fn main() {}
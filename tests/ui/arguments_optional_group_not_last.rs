@@ -0,0 +1,11 @@
+use katcp_derive::KatcpArguments;
+
+#[derive(KatcpArguments)]
+struct Capture {
+    #[katcp(flatten, skip_if_none)]
+    window: Option<(f64, f64)>,
+    #[katcp(skip_if_none)]
+    label: Option<String>,
+}
+
+fn main() {}
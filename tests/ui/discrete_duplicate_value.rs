@@ -0,0 +1,10 @@
+use katcp_derive::KatcpDiscrete;
+
+#[derive(KatcpDiscrete)]
+enum Mode {
+    Idle,
+    #[katcp(rename = "idle")]
+    Standby,
+}
+
+fn main() {}
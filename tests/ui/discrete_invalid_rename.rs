@@ -0,0 +1,10 @@
+use katcp_derive::KatcpDiscrete;
+
+#[derive(KatcpDiscrete)]
+enum Mode {
+    Idle,
+    #[katcp(rename = "full speed")]
+    FullSpeed,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use katcp_derive::KatcpDiscrete;
+
+#[derive(KatcpDiscrete)]
+enum Mode {
+    Idle,
+    Capture(u32),
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use katcp::prelude::*;
+use katcp_derive::KatcpMessage;
+
+#[derive(KatcpMessage)]
+enum SetGains {
+    Request {
+        #[katcp(flatten)]
+        gains: Vec<f64>,
+        channel: u32,
+    },
+    Reply(RetCode),
+}
+
+fn main() {}
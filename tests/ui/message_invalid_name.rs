@@ -0,0 +1,11 @@
+use katcp::prelude::*;
+use katcp_derive::KatcpMessage;
+
+#[derive(KatcpMessage)]
+#[katcp(name = "set rate")]
+enum SetRate {
+    Request { rate: u32 },
+    Reply(RetCode),
+}
+
+fn main() {}
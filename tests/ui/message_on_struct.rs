@@ -0,0 +1,8 @@
+use katcp_derive::KatcpMessage;
+
+#[derive(KatcpMessage)]
+struct Halt {
+    reason: String,
+}
+
+fn main() {}
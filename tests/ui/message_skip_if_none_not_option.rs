@@ -0,0 +1,13 @@
+use katcp::prelude::*;
+use katcp_derive::KatcpMessage;
+
+#[derive(KatcpMessage)]
+enum SetRate {
+    Request {
+        #[katcp(skip_if_none)]
+        rate: u32,
+    },
+    Reply(RetCode),
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use katcp::prelude::*;
+use katcp_derive::KatcpMessage;
+
+#[derive(KatcpMessage)]
+enum SetRate {
+    Request {
+        #[katcp(skip_if_none)]
+        channel: Option<u32>,
+        rate: u32,
+    },
+    Reply(RetCode),
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use katcp::prelude::*;
+use katcp_derive::KatcpMessage;
+
+#[derive(KatcpMessage)]
+enum SetRate {
+    #[katcp(rename = "request")]
+    Request { rate: u32 },
+    Reply(RetCode),
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use katcp::prelude::*;
+use katcp_derive::KatcpMessage;
+
+#[derive(KatcpMessage)]
+enum Halt {
+    Request,
+    Response(RetCode),
+    Inform,
+}
+
+fn main() {}
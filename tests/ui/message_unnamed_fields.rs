@@ -0,0 +1,10 @@
+use katcp::prelude::*;
+use katcp_derive::KatcpMessage;
+
+#[derive(KatcpMessage)]
+enum SetRate {
+    Request(u32, u32),
+    Reply(RetCode),
+}
+
+fn main() {}
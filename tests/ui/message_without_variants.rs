@@ -0,0 +1,6 @@
+use katcp_derive::KatcpMessage;
+
+#[derive(KatcpMessage)]
+enum Halt {}
+
+fn main() {}